@@ -1,125 +1,254 @@
-//Idea read in file
-//extract position into internal, maybe via fen
-//zobrist hash
-//lookup hash in table
-//if found add to vec the next move
-// Use Python for it
-
-use crate::fen_import::make_board;
-use crate::{generate_all_moves, Board};
+use crate::fen_import::start_pos;
+use crate::Board;
 use std::collections::HashMap;
-use std::fs::File;
-use std::{fs, io};
-use std::io::Write;
-use crate::move_gen_dir::move_gen::GenerationMode;
-
-pub fn make_openings() -> io::Result<()> {
-    let contents = fs::read_to_string(
-        r"C:\Users\David Meyer\RustroverProjects\ChessEngine\src\OpeningBook\fen.txt"
-    )?;
+use crate::move_gen_dir::move_gen::Move;
+use crate::pgn::{read_pgn_file, Game, GameResult};
+use crate::OpeningBook::polyglot::{encode_polyglot_move, PolyglotBook, PolyglotEntry};
+
+/// How often a move was played in a position and how those games ended, seen from the side that played it.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct MoveStats {
+    pub count: u32,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
 
-    let mut book: HashMap<u64, Vec<u16>> = HashMap::new();
-    let mut current_fens: Vec<String> = Vec::new();
+impl MoveStats {
+    /// Polyglot's own weighting, two points per win and one per draw. Lines without any
+    /// known results fall back to how often the move was played.
+    pub const fn points(&self) -> u32 {
+        if self.wins + self.draws + self.losses == 0 {
+            self.count
+        } else {
+            2 * self.wins + self.draws
+        }
+    }
+}
 
-    let startpos = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// Collects move statistics by Polyglot key and turns them into a book.
+#[derive(Debug, Clone, Default)]
+pub struct BookBuilder {
+    moves: HashMap<(u64, u16), MoveStats>,
+}
 
-    for (lineno, line) in contents.lines().enumerate() {
-        let trimmed = line.trim();
+impl BookBuilder {
+    pub fn new() -> Self {
+        BookBuilder { moves: HashMap::new() }
+    }
 
-        if trimmed.is_empty() {
-            continue;
+    /// Records `mv` played from `board` in a game that ended with `result`.
+    pub fn add_move(&mut self, board: &Board, mv: Move, result: GameResult) {
+        let stats = self.moves.entry((board.polyglot_key(), encode_polyglot_move(mv))).or_default();
+        stats.count += 1;
+        match (result, board.white_to_move) {
+            (GameResult::WhiteWin, true) | (GameResult::BlackWin, false) => stats.wins += 1,
+            (GameResult::WhiteWin, false) | (GameResult::BlackWin, true) => stats.losses += 1,
+            (GameResult::Draw, _) => stats.draws += 1,
+            (GameResult::Unknown, _) => {}
         }
+    }
 
-        if trimmed.contains('/') {
-            // FEN line
-            if current_fens.is_empty() {
-                // ensure startpos is always first
-                if trimmed != startpos {
-                    current_fens.push(startpos.to_string());
-                }
-            }
-            current_fens.push(trimmed.to_string());
-        } else {
-            // Opening name or separator
-            if current_fens.len() >= 2 {
-                process_fens(&current_fens, &mut book, lineno);
-            }
-            current_fens.clear();
-        }
+    /// Statistics of the recorded moves of `board`, most played first.
+    pub fn stats(&self, board: &Board) -> Vec<(Move, MoveStats)> {
+        let key = board.polyglot_key();
+        let mut stats: Vec<(Move, MoveStats)> = self
+            .moves
+            .iter()
+            .filter(|((move_key, _), _)| *move_key == key)
+            .filter_map(|((_, raw), stats)| board.decode_polyglot_move(*raw).map(|mv| (mv, *stats)))
+            .collect();
+        stats.sort_by_key(|(mv, stats)| (std::cmp::Reverse(stats.count), mv.to_u16()));
+        stats
     }
 
-    if current_fens.len() >= 2 {
-        process_fens(&current_fens, &mut book, contents.lines().count());
+    pub fn len(&self) -> usize {
+        self.moves.len()
     }
 
-    // sort + dedup moves
-    for moves in book.values_mut() {
-        moves.sort_unstable();
-        moves.dedup();
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
     }
 
-    // Write binary book
-    let mut file = File::create(
-        r"C:\Users\David Meyer\RustroverProjects\ChessEngine\src\OpeningBook\book.bin"
-    )?;
-    for (hash, moves) in &book {
-        file.write_all(&hash.to_le_bytes())?;
-        let len = moves.len() as u16;
-        file.write_all(&len.to_le_bytes())?;
-        for mv in moves {
-            file.write_all(&mv.to_le_bytes())?;
-        }
+    /// Moves played fewer than `min_count` times are left out. Weights are scaled down
+    /// if the points of some move don't fit into 16 bits.
+    pub fn to_polyglot(&self, min_count: u32) -> PolyglotBook {
+        let kept: Vec<(&(u64, u16), &MoveStats)> = self.moves.iter().filter(|(_, stats)| stats.count >= min_count).collect();
+        let max_points = kept.iter().map(|(_, stats)| stats.points()).max().unwrap_or(0).max(1) as u64;
+        let scale = |points: u32| if max_points > u16::MAX as u64 { (points as u64 * u16::MAX as u64 / max_points) as u16 } else { points as u16 };
+
+        PolyglotBook::from_entries(
+            kept.iter()
+                .map(|((key, mv), stats)| PolyglotEntry { key: *key, mv: *mv, weight: scale(stats.points()), learn: 0 })
+                .collect(),
+        )
     }
+}
 
-    Ok(())
+/// Which games and positions go into a book built from PGN.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BookFilter {
+    /// Only moves made before this ply are recorded.
+    pub max_ply: usize,
+    /// Both players need at least this `WhiteElo` / `BlackElo`, 0 also accepts games without ratings.
+    pub min_elo: u32,
+    /// Moves played fewer times are dropped when the book is written.
+    pub min_count: u32,
+    pub results: Vec<GameResult>,
 }
 
-fn process_fens(fens: &[String], book: &mut HashMap<u64, Vec<u16>>, lineno: usize) {
-    let mut prev = make_board(&fens[0]);
+impl BookFilter {
+    pub fn new() -> Self {
+        BookFilter { max_ply: 20, min_elo: 0, min_count: 1, results: vec![GameResult::WhiteWin, GameResult::BlackWin, GameResult::Draw] }
+    }
 
-    for fen in &fens[1..] {
-        let next = make_board(fen);
+    pub fn accepts(&self, game: &Game) -> bool {
+        let elo = |tag: &str| game.tag(tag).and_then(|elo| elo.parse::<u32>().ok()).unwrap_or(0);
+        self.results.contains(&game.result) && elo("WhiteElo").min(elo("BlackElo")) >= self.min_elo
+    }
+}
 
-        match find_move(&next, &mut prev) {
-            Some(mv) => {
-                let key = prev.zobrist_hash();
-                book.entry(key).or_default().push(mv);
-                prev = next;
-            }
-            None => {
-                eprintln!(
-                    "Line {}: couldn't find a legal single move from previous FEN to next FEN.",
-                    lineno + 1
-                );
-                prev = next;
-            }
+/// Replays the accepted games and records their moves up to `filter.max_ply`.
+/// Returns how many games were used.
+pub fn add_games(book: &mut BookBuilder, games: &[Game], filter: &BookFilter) -> usize {
+    let mut used = 0;
+    for game in games.iter().filter(|game| filter.accepts(game)) {
+        let mut board = game.start_position.clone();
+        for &mv in game.moves.iter().take(filter.max_ply) {
+            book.add_move(&board, mv, game.result);
+            board.make_move(mv);
         }
+        used += 1;
     }
+    used
 }
 
+/// `book <out.bin> <games.pgn>... [--max-ply N] [--min-elo N] [--min-count N] [--results 1-0,0-1,1/2-1/2]`
+/// builds a Polyglot book. Returns whether it was written.
+pub fn book_cli(args: &[String]) -> bool {
+    let usage = "usage: book <out.bin> <games.pgn>... [--max-ply N] [--min-elo N] [--min-count N] [--results 1-0,0-1,1/2-1/2]";
+    let mut filter = BookFilter::new();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--max-ply" => filter.max_ply = args.next().and_then(|ply| ply.parse().ok()).unwrap_or(filter.max_ply),
+            "--min-elo" => filter.min_elo = args.next().and_then(|elo| elo.parse().ok()).unwrap_or(filter.min_elo),
+            "--min-count" => filter.min_count = args.next().and_then(|count| count.parse().ok()).unwrap_or(filter.min_count),
+            "--results" => {
+                let results: Option<Vec<GameResult>> = args.next().map(|results| results.split(',').filter_map(GameResult::parse).collect());
+                match results {
+                    Some(results) if !results.is_empty() => filter.results = results,
+                    _ => {
+                        eprintln!("{}", usage);
+                        return false;
+                    }
+                }
+            }
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() < 2 {
+        eprintln!("{}", usage);
+        return false;
+    }
 
-fn find_move(next: &Board, prev: &mut Board) -> Option<u16> {
-    let list = generate_all_moves(prev, &GenerationMode::All); // prev is &mut, but this borrows immutably here
+    let mut book = BookBuilder::new();
+    let mut games_read = 0;
+    let mut games_used = 0;
+    for path in paths[1..].iter() {
+        let games = match read_pgn_file(path) {
+            Ok(games) => games,
+            Err(err) => {
+                eprintln!("{}: {}", path, err);
+                return false;
+            }
+        };
+        games_read += games.len();
+        games_used += add_games(&mut book, &games, &filter);
+    }
 
-    for i in 0..list.moves_added {
-        let mv = list.moves[i];
-        let info = prev.make_move(mv);
+    if book.is_empty() {
+        eprintln!("none of the {} games passed the filter, no book written", games_read);
+        return false;
+    }
 
-        let matches = prev.occ == next.occ; // compare board states
+    let polyglot = book.to_polyglot(filter.min_count);
+    if let Err(err) = polyglot.save(paths[0]) {
+        eprintln!("{}", err);
+        return false;
+    }
+    println!("{} of {} games used, {} of {} distinct moves written to {}", games_used, games_read, polyglot.len(), book.len(), paths[0]);
 
-        prev.undo_move(info);
+    // Results are from the side that played the move
+    let board = start_pos();
+    for (mv, stats) in book.stats(&board) {
+        println!("{:<6} {:>8} games  +{} ={} -{}", board.move_to_san(mv), stats.count, stats.wins, stats.draws, stats.losses);
+    }
+    true
+}
 
-        if matches {
-            return Some(pack_move(
-                mv.start_square.trailing_zeros() as u8,
-                mv.end_square.trailing_zeros() as u8,
-            ));
-        }
+#[cfg(test)]
+mod tests {
+    use super::{add_games, BookBuilder, BookFilter, MoveStats};
+    use crate::fen_import::start_pos;
+    use crate::pgn::{parse_pgn, GameResult};
+    use crate::OpeningBook::polyglot::encode_polyglot_move;
+
+    #[test]
+    fn records_results_from_the_movers_side() {
+        let mut board = start_pos();
+        let e4 = board.parse_san("e4").unwrap();
+        let d4 = board.parse_san("d4").unwrap();
+
+        let mut builder = BookBuilder::new();
+        builder.add_move(&board, e4, GameResult::WhiteWin);
+        builder.add_move(&board, e4, GameResult::Draw);
+        builder.add_move(&board, e4, GameResult::BlackWin);
+        builder.add_move(&board, d4, GameResult::Unknown);
+        board.make_move(e4);
+        let e5 = board.parse_san("e5").unwrap();
+        builder.add_move(&board, e5, GameResult::WhiteWin);
+
+        assert_eq!(builder.stats(&board), vec![(e5, MoveStats { count: 1, wins: 0, draws: 0, losses: 1 })]);
+        let e4_stats = MoveStats { count: 3, wins: 1, draws: 1, losses: 1 };
+        assert_eq!(builder.stats(&start_pos()), vec![(e4, e4_stats), (d4, MoveStats { count: 1, wins: 0, draws: 0, losses: 0 })]);
+        assert_eq!(e4_stats.points(), 3);
+
+        let book = builder.to_polyglot(2);
+        assert_eq!(book.len(), 1);
+        let entries = book.entries(start_pos().polyglot_key());
+        assert_eq!((entries[0].mv, entries[0].weight), (encode_polyglot_move(e4), 3));
+        assert_eq!(builder.to_polyglot(1).len(), 3);
     }
 
-    None
+    const GAMES: &str = "[WhiteElo \"2400\"]\n[BlackElo \"2300\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 1-0\n\n\
+                         [WhiteElo \"1500\"]\n[BlackElo \"2500\"]\n\n1. e4 c5 2. Nf3 d6 0-1\n\n\
+                         1. d4 d5 2. c4 e6 1/2-1/2\n\n1. e4 e5 *\n";
+
+    #[test]
+    fn builds_book_from_filtered_games() {
+        let games = parse_pgn(GAMES).unwrap();
+        let board = start_pos();
+        let e4 = board.parse_san("e4").unwrap();
+        let d4 = board.parse_san("d4").unwrap();
+
+        let mut builder = BookBuilder::new();
+        let filter = BookFilter { max_ply: 2, ..BookFilter::new() };
+        assert_eq!(add_games(&mut builder, &games, &filter), 3);
+        assert_eq!(builder.stats(&board), vec![(e4, MoveStats { count: 2, wins: 1, draws: 0, losses: 1 }), (d4, MoveStats { count: 1, wins: 0, draws: 1, losses: 0 })]);
+        // Two plies per game, the third move is never recorded
+        assert_eq!(builder.len(), 5);
+
+        let mut builder = BookBuilder::new();
+        let filter = BookFilter { min_elo: 2000, ..BookFilter::new() };
+        assert_eq!(add_games(&mut builder, &games, &filter), 1);
+        assert_eq!(builder.len(), 6);
+
+        let mut builder = BookBuilder::new();
+        let filter = BookFilter { results: vec![GameResult::Draw], ..BookFilter::new() };
+        assert_eq!(add_games(&mut builder, &games, &filter), 1);
+        assert_eq!(builder.stats(&board), vec![(d4, MoveStats { count: 1, wins: 0, draws: 1, losses: 0 })]);
+    }
 }
-
-fn pack_move(start: u8, end: u8) -> u16 {
-    ((start as u16) << 8) | (end as u16)
-}
\ No newline at end of file
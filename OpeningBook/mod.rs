@@ -1,2 +1,3 @@
 pub mod generate_opening_book;
+pub mod polyglot;
 pub mod work_with_opening_book;
\ No newline at end of file
@@ -2,10 +2,10 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io;
 use std::io::Read;
-use rand::{Open01, Rng};
+use rand::Rng;
 use crate::{generate_all_moves, Board};
 use crate::move_gen_dir::move_gen::{GenerationMode, Move};
-use crate::search::search::Searcher;
+use crate::OpeningBook::polyglot::{BookSelection, PolyglotBook, PolyglotError};
 
 pub fn unpack_move(m: u16) -> (u8, u8) {
     let start = (m >> 8) as u8;
@@ -28,6 +28,9 @@ pub fn load_opening_book(path: &str) -> io::Result<HashMap<u64, Vec<u16>>> {
         let count = u16::from_le_bytes(buf[i..i + 2].try_into().unwrap());
         i += 2;
 
+        if i + 2 * count as usize > buf.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "book entry runs past the end of the file"));
+        }
         let mut moves = Vec::with_capacity(count as usize);
         for _ in 0..count {
             let mv = u16::from_le_bytes(buf[i..i + 2].try_into().unwrap());
@@ -61,20 +64,42 @@ fn find_opening_moves(book: &HashMap<u64, Vec<u16>>, hash: u64, board: &mut Boar
     }
     opening_moves
 }
-pub fn find_opening_move(board: &mut Board)-> Option<Move> {
-    let opening_book: HashMap<u64,Vec<u16>> = load_opening_book(r"ToOpeningBook").unwrap();
+/// A book held in memory for the whole session, either Polyglot or the older format keyed by our own Zobrist hash.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum OpeningBook {
+    Polyglot(PolyglotBook),
+    Legacy(HashMap<u64, Vec<u16>>),
+}
 
-    let last_double_p_push = board.last_double_pawn_push;
-    board.last_double_pawn_push = 0;
-    let hash = board.zobrist_hash();
-    board.last_double_pawn_push = last_double_p_push;
+impl OpeningBook {
+    /// Polyglot books are recognised by their layout, anything else is read in the old format.
+    /// An empty file holds no moves in either, so it counts as no book at all.
+    pub fn load(path: &str) -> Result<Self, String> {
+        match PolyglotBook::load(path) {
+            Ok(book) if book.is_empty() => Err(format!("{} has no book moves", path)),
+            Ok(book) => Ok(OpeningBook::Polyglot(book)),
+            Err(PolyglotError::Io(err)) => Err(err),
+            Err(_) => load_opening_book(path).map(OpeningBook::Legacy).map_err(|err| format!("couldn't read {}: {}", path, err)),
+        }
+    }
 
+    /// The old format has no weights, so it only tells apart `Best`, which takes the first stored move, from random selection.
+    pub fn pick_move<R: Rng>(&self, board: &mut Board, selection: BookSelection, rng: &mut R) -> Option<Move> {
+        match self {
+            OpeningBook::Polyglot(book) => book.pick_move(board, selection, rng),
+            OpeningBook::Legacy(book) => {
+                let last_double_p_push = board.last_double_pawn_push;
+                board.last_double_pawn_push = 0;
+                let hash = board.compute_zobrist_hash();
+                board.last_double_pawn_push = last_double_p_push;
 
-    let found_opening_moves = find_opening_moves(&opening_book, hash, board);
-    if found_opening_moves.len() == 0 {
-        None
-    } else {
-        let num = rand::thread_rng().gen_range(0,found_opening_moves.len());
-        Some(found_opening_moves[num])
+                let found_opening_moves = find_opening_moves(book, hash, board);
+                match selection {
+                    _ if found_opening_moves.is_empty() => None,
+                    BookSelection::Best => Some(found_opening_moves[0]),
+                    BookSelection::Weighted | BookSelection::Uniform => Some(found_opening_moves[rng.gen_range(0, found_opening_moves.len())]),
+                }
+            }
+        }
     }
-}
\ No newline at end of file
+}
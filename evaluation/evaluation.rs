@@ -3,7 +3,7 @@ use crate::helpers::pop_lsb;
 use crate::move_gen_dir::move_gen::PieceType;
 use crate::Board;
 
-const PIECE_VALUES: [u32; 6] = [100, 300, 315, 500, 900, 0]; //Pawm, Knight, Bishop, Rook, Queen, King
+pub const PIECE_VALUES: [u32; 6] = [100, 300, 315, 500, 900, 0]; //Pawm, Knight, Bishop, Rook, Queen, King
 const ENDGAME_MATERIAL_START: u32 = PIECE_VALUES[3] * 2 + PIECE_VALUES[2] + PIECE_VALUES[1];
 
 pub fn evaluate_board(board: &Board) -> i32 {
@@ -1,2 +1,3 @@
 pub mod evaluation;
+pub mod see;
 pub mod square_piece_table;
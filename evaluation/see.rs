@@ -0,0 +1,145 @@
+use crate::evaluation::evaluation::PIECE_VALUES;
+use crate::move_gen_dir::move_gen::{get_bishop_attacks, get_rook_attacks, Move, PieceType};
+use crate::move_gen_dir::move_gen::PieceType::{Bishop, King, Knight, NoPiece, Pawn, Queen, Rook};
+use crate::Board;
+
+/// Cheapest attackers first, an exchange always continues with the least valuable piece
+const CAPTURE_ORDER: [PieceType; 6] = [Pawn, Knight, Bishop, Rook, Queen, King];
+/// Longest possible exchange on one square, every piece of both sides takes once
+const MAX_EXCHANGE_LENGTH: usize = 32;
+
+const fn see_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        Pawn => PIECE_VALUES[0] as i32,
+        Knight => PIECE_VALUES[1] as i32,
+        Bishop => PIECE_VALUES[2] as i32,
+        Rook => PIECE_VALUES[3] as i32,
+        Queen => PIECE_VALUES[4] as i32,
+        King => 20_000,
+        NoPiece => 0,
+    }
+}
+
+impl Board {
+    /// Static exchange evaluation: the material the side to move wins by playing `mv` when both sides keep
+    /// recapturing on its target square with their least valuable piece, and either side may stop.
+    /// Pins and checks are ignored, the king only takes when the square is no longer defended.
+    pub fn see(&self, mv: Move) -> i32 {
+        let target = mv.end_square.trailing_zeros() as usize;
+        let captured_square = if mv.en_passant { self.last_double_pawn_push } else { mv.end_square };
+        let mut occupancy = (self.occ & !mv.start_square & !captured_square) | mv.end_square;
+        let mut attacking = self.attackers_to(target, occupancy) & occupancy;
+
+        let mut gain = [0; MAX_EXCHANGE_LENGTH];
+        gain[0] = see_value(mv.capture);
+        let mut piece_on_target = mv.piece_type;
+        if mv.promotion != NoPiece {
+            gain[0] += see_value(mv.promotion) - see_value(Pawn);
+            piece_on_target = mv.promotion;
+        }
+
+        let mut white = !self.white_to_move;
+        let mut depth = 0;
+        while depth + 1 < MAX_EXCHANGE_LENGTH {
+            let own_attackers = attacking & self.pieces_of(white);
+            let Some((piece_type, square)) = CAPTURE_ORDER.iter()
+                .map(|&piece_type| (piece_type, own_attackers & self.get_pieces(piece_type, white)))
+                .find(|(_, pieces)| *pieces != 0)
+                .map(|(piece_type, pieces)| (piece_type, pieces & pieces.wrapping_neg()))
+            else {
+                break;
+            };
+            if piece_type == King && attacking & !own_attackers != 0 {
+                break;
+            }
+
+            depth += 1;
+            gain[depth] = see_value(piece_on_target) - gain[depth - 1];
+            piece_on_target = piece_type;
+
+            // Taking away the capturing piece can uncover a slider behind it
+            occupancy &= !square;
+            if matches!(piece_type, Pawn | Bishop | Queen) {
+                attacking |= get_bishop_attacks(target, occupancy) & (self.wbishop | self.bbishop | self.wqueen | self.bqueen);
+            }
+            if matches!(piece_type, Rook | Queen) {
+                attacking |= get_rook_attacks(target, occupancy) & (self.wrook | self.brook | self.wqueen | self.bqueen);
+            }
+            attacking &= occupancy;
+            white = !white;
+        }
+
+        // Going backwards, each side either takes or stops, whatever is better for it
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    /// Whether `see(mv) >= threshold`, without working out the exchange when the outcome is clear anyway.
+    pub fn see_ge(&self, mv: Move, threshold: i32) -> bool {
+        let mut gain = see_value(mv.capture);
+        let mut moved_value = see_value(mv.piece_type);
+        if mv.promotion != NoPiece {
+            gain += see_value(mv.promotion) - see_value(Pawn);
+            moved_value = see_value(mv.promotion);
+        }
+        // The opponent may decline to recapture, and we may stop after losing the moved piece
+        if gain < threshold {
+            return false;
+        }
+        if gain - moved_value >= threshold {
+            return true;
+        }
+        self.see(mv) >= threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fen_import::make_board;
+
+    fn see(fen: &str, san: &str) -> i32 {
+        let board = make_board(fen);
+        board.see(board.parse_san(san).unwrap())
+    }
+
+    #[test]
+    fn exchanges_follow_least_valuable_attackers_and_x_rays() {
+        // Undefended pawn
+        assert_eq!(see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "Rxe5"), 100);
+        // Knight for pawn, with rook, queen and bishop x-rays behind both sides
+        assert_eq!(see("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "Nxe5"), -200);
+        // Pawn takes, the queen recaptures and is lost to the rook behind the first capturer
+        assert_eq!(see("4k3/3q4/8/3p4/4P3/8/3R4/4K3 w - - 0 1", "exd5"), 100);
+        // The king can't recapture while the queen behind the rook defends the square
+        assert_eq!(see("8/8/4k3/3p4/8/8/3R4/3QK3 w - - 0 1", "Rxd5"), 100);
+        assert_eq!(see("8/8/4k3/3p4/8/8/3R4/4K3 w - - 0 1", "Rxd5"), -400);
+        // En passant and promotions
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "exd6"), 100);
+        assert_eq!(see("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "axb8=Q"), 500 + 800);
+        assert_eq!(see("2r1k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a8=Q"), -100);
+        // A quiet move into an attacked square loses the piece
+        assert_eq!(see("4k3/8/8/8/3p4/8/8/2N1K3 w - - 0 1", "Ne2"), 0);
+        assert_eq!(see("4k3/8/8/3p4/8/8/8/2N1K3 w - - 0 1", "Nb3"), 0);
+        assert_eq!(see("4k3/8/8/8/2p5/8/8/2N1K3 w - - 0 1", "Nb3"), -300);
+    }
+
+    #[test]
+    fn see_ge_agrees_with_see() {
+        let positions = [
+            ("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "Nxe5"),
+            ("4k3/3q4/8/3p4/4P3/8/3R4/4K3 w - - 0 1", "exd5"),
+            ("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "axb8=Q"),
+            ("4k3/8/8/8/2p5/8/8/2N1K3 w - - 0 1", "Nb3"),
+        ];
+        for (fen, san) in positions {
+            let board = make_board(fen);
+            let mv = board.parse_san(san).unwrap();
+            for threshold in [-1000, -300, -200, -1, 0, 1, 100, 1300, 2000] {
+                assert_eq!(board.see_ge(mv, threshold), board.see(mv) >= threshold, "{} {} {}", fen, san, threshold);
+            }
+        }
+    }
+}
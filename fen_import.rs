@@ -1,100 +1,369 @@
 use crate::Board;
+use crate::helpers::index_to_sq;
+use std::fmt;
 
 
 pub fn start_pos() -> Board {
     return make_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
 }
+/// For FENs we wrote ourselves, anything coming from outside should go through `Board::from_fen`.
 pub fn make_board(fen_string: &str) -> Board {
-    let binding = fen_string.replace("/", " ");
-    let split_string = binding.split_whitespace(); // More idiomatic
+    match Board::from_fen(fen_string) {
+        Ok(board) => board,
+        Err(error) => panic!("Invalid FEN {}: {}", fen_string, error),
+    }
+}
 
-    let mut white_to_move = false;
-    let mut castling_rights: u8 = 0;
-    let mut en_passant:u64 = 0;
-
-    let mut piece_array: [u64; 12] = [0; 12]; // White first: pawn, rook, knight, bishop, queen, king
-
-    let mut rank = 8; // Start from rank 8
-
-    for part in split_string {
-
-        if rank == 0 {
-            if part == "w" {
-                white_to_move = true
-            } else if part.contains("k") | part.contains("K") | part.contains("q") | part.contains("Q") {
-                castling_rights = extraxt_castling_rights(part);
-            } else if part.chars().any(|c| c >= 'a' && c <= 'h') {
-                en_passant = match part {
-                    "a3" => {0x1000000},
-                    "b3" => {0x2000000},
-                    "c3" => {0x4000000},
-                    "d3" => {0x8000000},
-                    "e3" => {0x10000000},
-                    "f3" => {0x20000000},
-                    "g3" => {0x40000000},
-                    "h3" => {0x80000000},
-                    "a6" => {0x100000000},
-                    "b6" => {0x200000000},
-                    "c6" => {0x400000000},
-                    "d6" => {0x800000000},
-                    "e6" => {0x1000000000},
-                    "f6" => {0x2000000000},
-                    "g6" => {0x4000000000},
-                    "h6" => {0x8000000000},
-                    _ => {0}
-                }
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(&'static str),
+    UnexpectedField(String),
+    WrongRankCount(usize),
+    WrongSquareCount { rank: usize, squares: usize },
+    InvalidPiece { rank: usize, piece: char },
+    InvalidSideToMove(String),
+    InvalidCastlingRights(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    WrongKingCount { white: u32, black: u32 },
+    PawnOnBackRank,
+    OpponentInCheck,
+    CastlingRightsWithoutPieces(char),
+    EnPassantWithoutPawn(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing {} field", field),
+            FenError::UnexpectedField(field) => write!(f, "unexpected field '{}' after the fullmove number", field),
+            FenError::WrongRankCount(ranks) => write!(f, "piece placement has {} ranks instead of 8", ranks),
+            FenError::WrongSquareCount { rank, squares } => write!(f, "piece placement: rank {} has {} squares instead of 8", rank, squares),
+            FenError::InvalidPiece { rank, piece } => write!(f, "piece placement: invalid piece '{}' on rank {}", piece, rank),
+            FenError::InvalidSideToMove(side) => write!(f, "side to move: expected 'w' or 'b', got '{}'", side),
+            FenError::InvalidCastlingRights(rights) => write!(f, "castling rights: invalid value '{}'", rights),
+            FenError::InvalidEnPassant(square) => write!(f, "en passant: invalid square '{}'", square),
+            FenError::InvalidHalfmoveClock(clock) => write!(f, "halfmove clock: invalid value '{}'", clock),
+            FenError::InvalidFullmoveNumber(number) => write!(f, "fullmove number: invalid value '{}'", number),
+            FenError::WrongKingCount { white, black } => write!(f, "piece placement: {} white and {} black kings, expected one each", white, black),
+            FenError::PawnOnBackRank => write!(f, "piece placement: pawn on the first or eighth rank"),
+            FenError::OpponentInCheck => write!(f, "side to move: the side not to move is in check"),
+            FenError::CastlingRightsWithoutPieces(right) => write!(f, "castling rights: '{}' without king and rook on their start squares", right),
+            FenError::EnPassantWithoutPawn(square) => write!(f, "en passant: no pawn could have just double pushed to pass '{}'", square),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl Board {
+    /// Parses and validates a FEN. The castling, en passant and counter fields may be left out, as in EPD.
+    pub fn from_fen(fen_string: &str) -> Result<Board, FenError> {
+        let mut fields = fen_string.split_whitespace();
+
+        let placement = fields.next().ok_or(FenError::MissingField("piece placement"))?;
+        let piece_array = parse_piece_placement(placement)?;
+
+        let white_to_move = match fields.next() {
+            Some("w") => true,
+            Some("b") => false,
+            Some(side) => return Err(FenError::InvalidSideToMove(side.to_string())),
+            None => return Err(FenError::MissingField("side to move")),
+        };
+        let castling_rights = extraxt_castling_rights(fields.next().unwrap_or("-"))?;
+        let en_passant = extract_en_passant(fields.next().unwrap_or("-"), white_to_move)?;
+
+        let halfmove_clock = match fields.next() {
+            Some(clock) => clock.parse().map_err(|_| FenError::InvalidHalfmoveClock(clock.to_string()))?,
+            None => 0,
+        };
+        let fullmove_number = match fields.next() {
+            Some(number) => match number.parse() {
+                Ok(number) if number >= 1 => number,
+                _ => return Err(FenError::InvalidFullmoveNumber(number.to_string())),
+            },
+            None => 1,
+        };
+        if let Some(field) = fields.next() {
+            return Err(FenError::UnexpectedField(field.to_string()));
+        }
+
+        let board = Board::new(piece_array[0], piece_array[1], piece_array[2], piece_array[3], piece_array[4], piece_array[5],
+                               piece_array[6], piece_array[7], piece_array[8], piece_array[9], piece_array[10], piece_array[11],
+                               white_to_move,
+                               castling_rights,
+                               en_passant,
+                               halfmove_clock,
+                               fullmove_number
+        );
+        board.validate()?;
+        Ok(board)
+    }
+
+    /// Checks that the position could occur in a game, as far as the move generator relies on it.
+    pub fn validate(&self) -> Result<(), FenError> {
+        if self.wking.count_ones() != 1 || self.bking.count_ones() != 1 {
+            return Err(FenError::WrongKingCount { white: self.wking.count_ones(), black: self.bking.count_ones() });
+        }
+        if (self.wpawn | self.bpawn) & 0xff000000000000ff != 0 {
+            return Err(FenError::PawnOnBackRank);
+        }
+
+        // King on its start square and rook in the corner for every right
+        for (right, right_char, king, king_square, rook, rook_square) in [
+            (1, 'K', self.wking, 0x10, self.wrook, 0x80),
+            (2, 'Q', self.wking, 0x10, self.wrook, 0x1),
+            (4, 'k', self.bking, 0x1000000000000000, self.brook, 0x8000000000000000),
+            (8, 'q', self.bking, 0x1000000000000000, self.brook, 0x100000000000000),
+        ] {
+            if self.castling_rights & right != 0 && (king & king_square == 0 || rook & rook_square == 0) {
+                return Err(FenError::CastlingRightsWithoutPieces(right_char));
             }
-        } else {
-            let mut file = 0;
-
-            for piece_char in part.chars() {
-                if piece_char.is_digit(10) {
-                    file += piece_char.to_digit(10).unwrap() as usize;
-                } else {
-                    let white_offset = if piece_char.is_ascii_uppercase() { 6 } else { 0 };
-                    let piece_index = get_piece(piece_char) + white_offset;
+        }
+
+        if self.last_double_pawn_push != 0 {
+            let (pushed_pawns, skipped_square, start_square) = if self.white_to_move {
+                (self.bpawn, self.last_double_pawn_push << 8, self.last_double_pawn_push << 16)
+            } else {
+                (self.wpawn, self.last_double_pawn_push >> 8, self.last_double_pawn_push >> 16)
+            };
+            if pushed_pawns & self.last_double_pawn_push == 0 || self.occ & (skipped_square | start_square) != 0 {
+                let square = index_to_sq(skipped_square.trailing_zeros() as usize).to_string().to_lowercase();
+                return Err(FenError::EnPassantWithoutPawn(square));
+            }
+        }
+
+        // The side to move could capture the king
+        let opponent_king = if self.white_to_move { self.bking } else { self.wking };
+        if self.is_attacked(opponent_king.trailing_zeros() as usize, self.white_to_move) {
+            return Err(FenError::OpponentInCheck);
+        }
+        Ok(())
+    }
+}
+
+fn parse_piece_placement(placement: &str) -> Result<[u64; 12], FenError> {
+    let mut piece_array: [u64; 12] = [0; 12]; // Black first: pawn, knight, bishop, rook, queen, king
+
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(FenError::WrongRankCount(ranks.len()));
+    }
+
+    for (rank_idx, part) in ranks.iter().enumerate() {
+        let rank = 8 - rank_idx; // Start from rank 8
+        let mut file = 0;
+
+        for piece_char in part.chars() {
+            if let Some(empty_squares @ 1..=8) = piece_char.to_digit(10) {
+                file += empty_squares as usize;
+            } else {
+                let white_offset = if piece_char.is_ascii_uppercase() { 6 } else { 0 };
+                let piece_index = get_piece(piece_char).ok_or(FenError::InvalidPiece { rank, piece: piece_char })? + white_offset;
+                if file < 8 {
                     let bit_index = (rank-1) * 8 + file;
                     piece_array[piece_index] |= 1 << bit_index;
-                    file += 1;
                 }
+                file += 1;
             }
-            rank -= 1;
+        }
+        if file != 8 {
+            return Err(FenError::WrongSquareCount { rank, squares: file });
         }
     }
+    Ok(piece_array)
+}
 
-    return Board::new(piece_array[0], piece_array[1], piece_array[2], piece_array[3], piece_array[4], piece_array[5],
-                      piece_array[6], piece_array[7], piece_array[8], piece_array[9], piece_array[10], piece_array[11],
-                      white_to_move,
-                      castling_rights,
-                      en_passant
-    );
+// The board stores the square of the pawn that just double pushed, FEN the square behind it
+fn extract_en_passant(part: &str, white_to_move: bool) -> Result<u64, FenError> {
+    let en_passant = match (part, white_to_move) {
+        ("-", _) => {0},
+        ("a3", false) => {0x1000000},
+        ("b3", false) => {0x2000000},
+        ("c3", false) => {0x4000000},
+        ("d3", false) => {0x8000000},
+        ("e3", false) => {0x10000000},
+        ("f3", false) => {0x20000000},
+        ("g3", false) => {0x40000000},
+        ("h3", false) => {0x80000000},
+        ("a6", true) => {0x100000000},
+        ("b6", true) => {0x200000000},
+        ("c6", true) => {0x400000000},
+        ("d6", true) => {0x800000000},
+        ("e6", true) => {0x1000000000},
+        ("f6", true) => {0x2000000000},
+        ("g6", true) => {0x4000000000},
+        ("h6", true) => {0x8000000000},
+        _ => return Err(FenError::InvalidEnPassant(part.to_string())),
+    };
+    Ok(en_passant)
 }
 
-fn extraxt_castling_rights(part: &str) -> u8 {
-    let mut castling_rights: u8 = 0;
-    if part.contains("K") {
-        castling_rights |= 1;
-    }
-    if part.contains("Q") {
-        castling_rights |= 2;
+impl Board {
+    pub fn to_fen(&self) -> String {
+        let pieces = [
+            (self.wpawn, 'P'), (self.wknight, 'N'), (self.wbishop, 'B'), (self.wrook, 'R'), (self.wqueen, 'Q'), (self.wking, 'K'),
+            (self.bpawn, 'p'), (self.bknight, 'n'), (self.bbishop, 'b'), (self.brook, 'r'), (self.bqueen, 'q'), (self.bking, 'k'),
+        ];
+        let mut fen = String::new();
+
+        for rank in (0..8).rev() {
+            let mut empty_squares = 0;
+            for file in 0..8 {
+                let square = 1u64 << (rank * 8 + file);
+                match pieces.iter().find(|(bitboard, _)| bitboard & square != 0) {
+                    Some((_, piece_char)) => {
+                        if empty_squares != 0 {
+                            fen.push_str(&empty_squares.to_string());
+                            empty_squares = 0;
+                        }
+                        fen.push(*piece_char);
+                    }
+                    None => empty_squares += 1,
+                }
+            }
+            if empty_squares != 0 {
+                fen.push_str(&empty_squares.to_string());
+            }
+            if rank != 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push_str(if self.white_to_move { " w " } else { " b " });
+
+        let castling: String = [(1, 'K'), (2, 'Q'), (4, 'k'), (8, 'q')].iter()
+            .filter(|(right, _)| self.castling_rights & right != 0)
+            .map(|(_, right_char)| *right_char)
+            .collect();
+        fen.push_str(if castling.is_empty() { "-" } else { &castling });
+
+        if self.last_double_pawn_push == 0 {
+            fen.push_str(" -");
+        } else {
+            let pawn_square = self.last_double_pawn_push.trailing_zeros() as usize;
+            let target_square = if pawn_square < 32 { pawn_square - 8 } else { pawn_square + 8 };
+            fen.push(' ');
+            fen.push_str(&index_to_sq(target_square).to_string().to_lowercase());
+        }
+
+        fen.push_str(&format!(" {} {}", self.halfmove_clock, self.fullmove_number));
+        fen
     }
-    if part.contains("k") {
-        castling_rights |= 4;
+}
+
+fn extraxt_castling_rights(part: &str) -> Result<u8, FenError> {
+    if part == "-" {
+        return Ok(0);
     }
-    if part.contains("q") {
-        castling_rights |= 8;
+    let mut castling_rights: u8 = 0;
+    for right_char in part.chars() {
+        let right = match right_char {
+            'K' => 1,
+            'Q' => 2,
+            'k' => 4,
+            'q' => 8,
+            _ => return Err(FenError::InvalidCastlingRights(part.to_string())),
+        };
+        if castling_rights & right != 0 {
+            return Err(FenError::InvalidCastlingRights(part.to_string()));
+        }
+        castling_rights |= right;
     }
-    return castling_rights;
+    Ok(castling_rights)
 }
 
-fn get_piece(piece_char: char) -> usize {
+fn get_piece(piece_char: char) -> Option<usize> {
     match piece_char.to_ascii_lowercase() {
-        'p' => 0,
-        'n' => 1,
-        'b' => 2,
-        'r' => 3,
-        'q' => 4,
-        'k' => 5,
-        _ => panic!("Invalid piece character: {}", piece_char),
+        'p' => Some(0),
+        'n' => Some(1),
+        'b' => Some(2),
+        'r' => Some(3),
+        'q' => Some(4),
+        'k' => Some(5),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_board, FenError};
+    use crate::move_gen_dir::move_gen::GenerationMode;
+    use crate::move_gen_dir::move_gen_tests::{for_each_position, TEST_POSITIONS};
+    use crate::search::repition_table::RepetitionTable;
+    use crate::uci::convert_mv_to_uci;
+    use crate::{generate_all_moves, Board};
+
+    #[test]
+    fn fen_round_trips_on_perft_positions() {
+        for fen in TEST_POSITIONS {
+            let mut board = make_board(fen);
+            assert_eq!(board.to_fen(), fen);
+            // Every position reached must survive Board -> FEN -> Board
+            for_each_position(&mut board, 2, &mut |board| {
+                let fen = board.to_fen();
+                assert_eq!(make_board(&fen), Board { position_history: RepetitionTable::new(), ..board.clone() }, "{}", fen);
+            });
+        }
+    }
+
+    #[test]
+    fn counters_follow_moves() {
+        let mut board = make_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        for (mv, fen) in [
+            ("e2e4", "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+            ("g8f6", "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2"),
+            ("g1f3", "rnbqkb1r/pppppppp/5n2/8/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 2 2"),
+            ("f6e4", "rnbqkb1r/pppppppp/8/8/4n3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3"),
+        ] {
+            let move_list = generate_all_moves(&board, &GenerationMode::All);
+            let found = (0..move_list.moves_added)
+                .map(|i| move_list.moves[i])
+                .find(|m| convert_mv_to_uci(*m) == mv)
+                .unwrap();
+            board.make_move(found);
+            assert_eq!(board.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn from_fen_rejects_malformed_fields() {
+        let cases = [
+            ("", FenError::MissingField("piece placement")),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", FenError::MissingField("side to move")),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1", FenError::WrongRankCount(7)),
+            ("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::InvalidPiece { rank: 6, piece: '9' }),
+            ("rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", FenError::WrongSquareCount { rank: 7, squares: 7 }),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNRR w KQkq - 0 1", FenError::WrongSquareCount { rank: 1, squares: 9 }),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKXNR w KQkq - 0 1", FenError::InvalidPiece { rank: 1, piece: 'X' }),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1", FenError::InvalidSideToMove("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1", FenError::InvalidCastlingRights("KQkx".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e4 0 1", FenError::InvalidEnPassant("e4".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1", FenError::InvalidHalfmoveClock("x".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0", FenError::InvalidFullmoveNumber("0".to_string())),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 moves", FenError::UnexpectedField("moves".to_string())),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(Board::from_fen(fen), Err(error), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn from_fen_rejects_illegal_positions() {
+        let cases = [
+            ("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1", FenError::WrongKingCount { white: 1, black: 0 }),
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBKKBNR w kq - 0 1", FenError::WrongKingCount { white: 2, black: 1 }),
+            ("rnbqkbnP/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNR w KQq - 0 1", FenError::PawnOnBackRank),
+            ("4k3/8/8/8/8/8/8/4K2R w Q - 0 1", FenError::CastlingRightsWithoutPieces('Q')),
+            ("4k3/8/8/8/8/8/8/R3K2R w KQk - 0 1", FenError::CastlingRightsWithoutPieces('k')),
+            ("4k3/8/8/8/8/8/8/4K2R w - e6 0 1", FenError::EnPassantWithoutPawn("e6".to_string())),
+            ("4k3/4R3/8/8/8/8/8/4K3 w - - 0 1", FenError::OpponentInCheck),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(Board::from_fen(fen), Err(error), "{}", fen);
+        }
+        assert!(Board::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").is_ok());
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 b -").is_ok());
     }
 }
@@ -11,12 +11,13 @@ use crate::move_gen_dir::precomputed_magics::{SQUARES_BETWEEN_DIAGONAL, SQUARES_
 use crate::move_list::MoveList;
 use crate::search::repition_table::RepetitionTable;
 use crate::uci::uci_loop;
+use crate::uci_options::EngineOptions;
 use colored::Colorize;
 use move_gen_dir::move_gen::PieceType::{King, Pawn};
 use crate::fen_import::{make_board, start_pos};
-use crate::move_gen_dir::move_gen_tests::_test_move_gen;
-use crate::OpeningBook::generate_opening_book::make_openings;
-use crate::OpeningBook::work_with_opening_book::{find_opening_move, get_book_moves, load_opening_book};
+use crate::move_gen_dir::perft::perft_cli;
+use crate::OpeningBook::generate_opening_book::book_cli;
+use crate::pgn::annotate_cli;
 use crate::search::search::Searcher;
 
 mod fen_import;
@@ -25,12 +26,15 @@ mod pregenerate_functions;
 mod move_list;
 mod generating_magics;
 mod uci;
+mod pgn;
+mod san;
+mod uci_options;
 mod zobrist_hashing;
 mod OpeningBook;
 mod evaluation;
 mod search;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Board {
     pub bpawn: u64,
     pub bknight: u64,
@@ -50,6 +54,9 @@ pub struct Board {
     pub castling_rights: u8, // First White King Second White Queen, This Black King, Fourth Black Queen
     pub last_double_pawn_push: u64,
     pub white_to_move: bool,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    pub zobrist_key: u64,
     pub position_history: RepetitionTable,
 }
 
@@ -59,6 +66,8 @@ pub struct MoveInfo {
     pub captured_piece: PieceType,
     pub castling_rights: u8,
     pub last_double_pawn_push: u64,
+    pub halfmove_clock: u32,
+    pub zobrist_key: u64,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -70,17 +79,19 @@ pub enum GameState {
 }
 
 impl Board {
-    pub const fn new(
+    pub fn new(
         bp: u64, bn: u64, bb: u64, br: u64, bq: u64, bk: u64,
         wp: u64, wn: u64, wb: u64, wr: u64, wq: u64, wk: u64,
         white_to_move: bool,
         castling_rights: u8,
         en_passant: u64,
+        halfmove_clock: u32,
+        fullmove_number: u32,
     ) -> Self {
         let black = bp | bn | bb | br | bq | bk;
         let white = wp | wn | wb | wr | wq | wk;
         let occ = black | white;
-        Self {
+        let mut board = Self {
             bpawn: bp, bknight: bn, bbishop: bb, brook: br, bqueen: bq, bking: bk,
             wpawn: wp, wknight: wn, wbishop: wb, wrook: wr, wqueen: wq, wking: wk,
             black,
@@ -89,8 +100,13 @@ impl Board {
             white_to_move,
             last_double_pawn_push: en_passant,
             castling_rights,
+            halfmove_clock,
+            fullmove_number,
+            zobrist_key: 0,
             position_history: RepetitionTable::new(),
-        }
+        };
+        board.zobrist_key = board.compute_zobrist_hash();
+        board
     }
     pub fn print_board(&self) {
         let mut board_chars = [' '; 64];
@@ -141,6 +157,7 @@ impl Board {
     }
 
     pub fn make_move(&mut self, mv: Move)-> MoveInfo {
+        self.position_history.add(self.zobrist_hash());
         let move_mask = mv.start_square | mv.end_square;
 
         let move_board: &mut u64 = match (self.white_to_move, mv.piece_type) {
@@ -226,12 +243,25 @@ impl Board {
             Castling::NoCastle => {},
         }
 
+        let pre_halfmove_clock = self.halfmove_clock;
+        if mv.piece_type == Pawn || mv.capture != PieceType::NoPiece {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if !self.white_to_move {
+            self.fullmove_number += 1;
+        }
+
         self.white_to_move = !self.white_to_move;
 
         self.set_occ();
-        self.position_history.add(self.zobrist_hash());
 
-        return MoveInfo{ last_move: mv, captured_piece: mv.capture, castling_rights: not_updated_castling_rights, last_double_pawn_push: pre_last_double_pawn_push };
+        let pre_zobrist_key = self.zobrist_key;
+        self.update_zobrist_key(mv, not_updated_castling_rights, pre_last_double_pawn_push);
+        debug_assert_eq!(self.zobrist_key, self.compute_zobrist_hash());
+
+        MoveInfo{ last_move: mv, captured_piece: mv.capture, castling_rights: not_updated_castling_rights, last_double_pawn_push: pre_last_double_pawn_push, halfmove_clock: pre_halfmove_clock, zobrist_key: pre_zobrist_key }
     }
 
     fn set_occ(&mut self) {
@@ -335,32 +365,109 @@ impl Board {
 
         self.last_double_pawn_push = last_mv.last_double_pawn_push;
 
+        self.halfmove_clock = last_mv.halfmove_clock;
+        self.zobrist_key = last_mv.zobrist_key;
+        if self.white_to_move {
+            self.fullmove_number -= 1;
+        }
+
         self.set_occ();
 
         self.position_history.pop_last();
 
         self.white_to_move = !self.white_to_move;
+        debug_assert_eq!(self.zobrist_key, self.compute_zobrist_hash());
     }
 
-    pub fn game_state(&mut self, moves: &MoveList) -> GameState {
-        if self.position_history.contains(self.zobrist_hash()) {
-            return GameState::Draw
+    /// Passes the turn for null-move pruning. En passant is cleared and the halfmove clock restarts,
+    /// so repetition detection doesn't look back across the null move.
+    pub fn make_null_move(&mut self) -> MoveInfo {
+        self.position_history.add(self.zobrist_hash());
+        let info = MoveInfo {
+            last_move: Move { start_square: 0, end_square: 0, capture: PieceType::NoPiece, piece_type: PieceType::NoPiece, promotion: PieceType::NoPiece, castle: Castling::NoCastle, en_passant: false },
+            captured_piece: PieceType::NoPiece,
+            castling_rights: self.castling_rights,
+            last_double_pawn_push: self.last_double_pawn_push,
+            halfmove_clock: self.halfmove_clock,
+            zobrist_key: self.zobrist_key,
+        };
+
+        self.clear_en_passant();
+        self.halfmove_clock = 0;
+        if !self.white_to_move {
+            self.fullmove_number += 1;
+        }
+        self.white_to_move = !self.white_to_move;
+        self.toggle_side_to_move_key();
+        debug_assert_eq!(self.zobrist_key, self.compute_zobrist_hash());
+        info
+    }
+
+    pub fn undo_null_move(&mut self, last_mv: MoveInfo) {
+        self.white_to_move = !self.white_to_move;
+        if !self.white_to_move {
+            self.fullmove_number -= 1;
         }
-        if moves.moves_added != 0 {
-            GameState::Ongoing
+        self.last_double_pawn_push = last_mv.last_double_pawn_push;
+        self.halfmove_clock = last_mv.halfmove_clock;
+        self.zobrist_key = last_mv.zobrist_key;
+        self.position_history.pop_last();
+        debug_assert_eq!(self.zobrist_key, self.compute_zobrist_hash());
+    }
+
+    /// True if the side to move has nothing but king and pawns, where passing is often the best move.
+    pub const fn has_only_pawns(&self) -> bool {
+        let (own, pawns_and_king) = if self.white_to_move {
+            (self.white, self.wpawn | self.wking)
         } else {
-            let (checkmask, _pinmask) = get_checkmask(self, &SQUARES_BETWEEN_STRAIGHT, &SQUARES_BETWEEN_DIAGONAL);
+            (self.black, self.bpawn | self.bking)
+        };
+        own & !pawns_and_king == 0
+    }
 
-            if checkmask == 0xffffffffffffffff {
+    pub fn game_state(&self, moves: &MoveList) -> GameState {
+        // Checkmate takes precedence, a mate delivered on the hundredth halfmove still wins
+        if moves.moves_added == 0 {
+            return if !self.in_check() {
                 GameState::Draw
             } else {
                 match self.white_to_move {
                     false => GameState::WhiteWin,
                     true => GameState::BlackWin,
                 }
-            }
+            };
+        }
+        if self.is_threefold_repetition() || self.is_fifty_move_draw() || self.has_insufficient_material() {
+            return GameState::Draw
         }
+        GameState::Ongoing
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.position_history.is_threefold(self.zobrist_hash(), self.halfmove_clock)
+    }
 
+    pub fn is_repetition_in_search(&self, zobrist_hash: u64, ply_from_root: usize) -> bool {
+        self.position_history.is_draw_in_search(zobrist_hash, self.halfmove_clock, ply_from_root)
+    }
+
+    pub const fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    /// K vs K, a single minor piece against a bare king, or only bishops which all stand on the same color.
+    pub const fn has_insufficient_material(&self) -> bool {
+        const DARK_SQUARES: u64 = 0xAA55AA55AA55AA55;
+
+        if (self.wpawn | self.bpawn | self.wrook | self.brook | self.wqueen | self.bqueen) != 0 {
+            return false;
+        }
+        let knights = self.wknight | self.bknight;
+        let bishops = self.wbishop | self.bbishop;
+        if (knights | bishops).count_ones() <= 1 {
+            return true;
+        }
+        knights == 0 && (bishops & DARK_SQUARES == 0 || bishops & !DARK_SQUARES == 0)
     }
 
     pub const fn get_pieces(&self, piece_type: PieceType, white_to_move: bool) -> u64 {
@@ -383,9 +490,38 @@ impl Board {
 }
 
 fn main() {
-    uci_loop();
-    // time_move_gen();
-
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("perft") => {
+            if !perft_cli(&args[2..]) {
+                std::process::exit(1);
+            }
+        }
+        Some("book") => {
+            if !book_cli(&args[2..]) {
+                std::process::exit(1);
+            }
+        }
+        Some("annotate") => {
+            if !annotate_cli(&args[2..]) {
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            let mut options = EngineOptions::new();
+            // `--book <file>` picks the opening book before any GUI is attached
+            if let Some(idx) = args.iter().position(|arg| arg == "--book") {
+                match args.get(idx + 1) {
+                    Some(book_file) => options.set_book_file(book_file.clone()),
+                    None => {
+                        eprintln!("usage: --book <file>");
+                        std::process::exit(1);
+                    }
+                }
+            }
+            uci_loop(options)
+        }
+    }
     // use std::time::Instant;
     // let now = Instant::now();
     // let mut board = make_board("3r2k1/5ppp/8/8/8/8/2R2PPP/6K1 b - - 0 1");
@@ -402,32 +538,25 @@ fn main() {
     // println!("Elapsed: {:.2?}", elapsed);
 }
 
-fn time_move_gen() {
-    use std::time::Instant;
-    let now = Instant::now();
-
-    _test_move_gen();
 
-    let elapsed = now.elapsed();
-    println!("Elapsed: {:.2?}", elapsed);
-}
-
-
-fn generate_all_moves(board: &mut Board, generation_mode: &GenerationMode) -> MoveList {
+fn generate_all_moves(board: &Board, generation_mode: &GenerationMode) -> MoveList {
     let mut move_list = MoveList::new();
+    append_moves(board, &mut move_list, generation_mode);
+    move_list
+}
 
+/// Adds the legal moves of `generation_mode` behind the moves already in `move_list`.
+fn append_moves(board: &Board, move_list: &mut MoveList, generation_mode: &GenerationMode) {
     let (checkmask, pinmask) = get_checkmask(board, &SQUARES_BETWEEN_STRAIGHT, &SQUARES_BETWEEN_DIAGONAL);
 
     if checkmask == 0 {
-        gen_king_moves(&board, &mut move_list, &checkmask, generation_mode);
+        gen_king_moves(board, move_list, &checkmask, generation_mode);
     } else {
-        generate_pawn_moves(&board, &mut move_list, &checkmask, &pinmask, generation_mode);
-        generate_knight_moves(&board, &mut move_list, &checkmask, &pinmask, generation_mode);
-        generate_bishop_moves(&board, if board.white_to_move { board.wbishop } else { board.bbishop }, PieceType::Bishop, &mut move_list, &checkmask, &pinmask, generation_mode);
-        generate_rook_moves(&board, if board.white_to_move { board.wrook } else { board.brook }, PieceType::Rook, &mut move_list, &checkmask, &pinmask, generation_mode);
-        generate_queen_moves(board, &mut move_list, &checkmask, &pinmask, generation_mode);
-        gen_king_moves(&board, &mut move_list, &checkmask, generation_mode);
+        generate_pawn_moves(board, move_list, &checkmask, &pinmask, generation_mode);
+        generate_knight_moves(board, move_list, &checkmask, &pinmask, generation_mode);
+        generate_bishop_moves(board, if board.white_to_move { board.wbishop } else { board.bbishop }, PieceType::Bishop, move_list, &checkmask, &pinmask, generation_mode);
+        generate_rook_moves(board, if board.white_to_move { board.wrook } else { board.brook }, PieceType::Rook, move_list, &checkmask, &pinmask, generation_mode);
+        generate_queen_moves(board, move_list, &checkmask, &pinmask, generation_mode);
+        gen_king_moves(board, move_list, &checkmask, generation_mode);
     }
-
-    move_list
 }
\ No newline at end of file
@@ -0,0 +1,143 @@
+use crate::Board;
+use crate::helpers::pop_lsb;
+use crate::move_gen_dir::knight_move_gen::KNIGHT_MOVES;
+use crate::move_gen_dir::move_gen::{get_bishop_attacks, get_rook_attacks, KING_MOVES};
+use crate::move_gen_dir::move_gen::PieceType::{Bishop, King, Knight, Pawn, Queen, Rook};
+use crate::move_gen_dir::precomputed_magics::{PAWN_ATTACKS_BLACK, PAWN_ATTACKS_WHITE, SQUARES_BETWEEN_DIAGONAL, SQUARES_BETWEEN_STRAIGHT};
+
+impl Board {
+    pub const fn pieces_of(&self, white: bool) -> u64 {
+        if white { self.white } else { self.black }
+    }
+
+    /// Pieces of both sides attacking `square`. Sliders look through everything missing from `occupancy`,
+    /// so leaving out a piece that moves away or is exchanged uncovers the x-rays behind it.
+    pub fn attackers_to(&self, square: usize, occupancy: u64) -> u64 {
+        let queens = self.wqueen | self.bqueen;
+        // A pawn attacks `square` from where a pawn of the other colour on `square` would attack
+        (PAWN_ATTACKS_BLACK[square] & self.wpawn)
+            | (PAWN_ATTACKS_WHITE[square] & self.bpawn)
+            | (KNIGHT_MOVES[square] & (self.wknight | self.bknight))
+            | (KING_MOVES[square] & (self.wking | self.bking))
+            | (get_bishop_attacks(square, occupancy) & (self.wbishop | self.bbishop | queens))
+            | (get_rook_attacks(square, occupancy) & (self.wrook | self.brook | queens))
+    }
+
+    pub fn is_attacked(&self, square: usize, by_white: bool) -> bool {
+        self.attackers_to(square, self.occ) & self.pieces_of(by_white) != 0
+    }
+
+    /// The enemy pieces giving check to the side to move.
+    pub fn checkers(&self) -> u64 {
+        let king = self.get_pieces(King, self.white_to_move);
+        if king == 0 {
+            return 0;
+        }
+        self.attackers_to(king.trailing_zeros() as usize, self.occ) & self.pieces_of(!self.white_to_move)
+    }
+
+    pub fn in_check(&self) -> bool {
+        self.checkers() != 0
+    }
+
+    /// Pieces of the side to move which are the only thing between their king and an enemy slider.
+    pub fn pinned(&self) -> u64 {
+        let white = self.white_to_move;
+        let king = self.get_pieces(King, white);
+        if king == 0 {
+            return 0;
+        }
+        let king_square = king.trailing_zeros() as usize;
+        let queens = self.get_pieces(Queen, !white);
+        let mut snipers = (get_rook_attacks(king_square, 0) & (self.get_pieces(Rook, !white) | queens))
+            | (get_bishop_attacks(king_square, 0) & (self.get_pieces(Bishop, !white) | queens));
+
+        let mut pinned = 0;
+        while snipers != 0 {
+            let sniper_square = pop_lsb(&mut snipers) as usize;
+            let between = (SQUARES_BETWEEN_STRAIGHT[king_square][sniper_square] | SQUARES_BETWEEN_DIAGONAL[king_square][sniper_square])
+                & self.occ & !king & !(1 << sniper_square);
+            if between.count_ones() == 1 {
+                pinned |= between & self.pieces_of(white);
+            }
+        }
+        pinned
+    }
+
+    /// Every square the given side attacks, whether it is empty, defended or occupied by the enemy.
+    pub fn attacks(&self, white: bool) -> u64 {
+        let pawns = self.get_pieces(Pawn, white);
+        let mut attacks = if white {
+            ((pawns & 0xfefefefefefefefe) << 7) | ((pawns & 0x7f7f7f7f7f7f7f7f) << 9)
+        } else {
+            ((pawns & 0x7f7f7f7f7f7f7f7f) >> 7) | ((pawns & 0xfefefefefefefefe) >> 9)
+        };
+
+        let mut knights = self.get_pieces(Knight, white);
+        while knights != 0 {
+            attacks |= KNIGHT_MOVES[pop_lsb(&mut knights) as usize];
+        }
+        let queens = self.get_pieces(Queen, white);
+        let mut diagonal_sliders = self.get_pieces(Bishop, white) | queens;
+        while diagonal_sliders != 0 {
+            attacks |= get_bishop_attacks(pop_lsb(&mut diagonal_sliders) as usize, self.occ);
+        }
+        let mut straight_sliders = self.get_pieces(Rook, white) | queens;
+        while straight_sliders != 0 {
+            attacks |= get_rook_attacks(pop_lsb(&mut straight_sliders) as usize, self.occ);
+        }
+        let king = self.get_pieces(King, white);
+        if king != 0 {
+            attacks |= KING_MOVES[king.trailing_zeros() as usize];
+        }
+        attacks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fen_import::make_board;
+    use crate::move_gen_dir::check_mask::get_checkmask;
+    use crate::move_gen_dir::move_gen::PieceType;
+    use crate::move_gen_dir::move_gen_tests::{for_each_position, CHECK_POSITIONS, TEST_POSITIONS};
+    use crate::move_gen_dir::precomputed_magics::{SQUARES_BETWEEN_DIAGONAL, SQUARES_BETWEEN_STRAIGHT};
+    use crate::Board;
+
+    fn assert_attack_queries(board: &mut Board) {
+        let (checkmask, _pinmask) = get_checkmask(board, &SQUARES_BETWEEN_STRAIGHT, &SQUARES_BETWEEN_DIAGONAL);
+        let expected_checkers = match checkmask {
+            0xffffffffffffffff => 0,
+            0 => 2,
+            _ => 1,
+        };
+        assert_eq!(board.checkers().count_ones(), expected_checkers, "{}", board.to_fen());
+
+        // A pinned piece is one whose removal lets another enemy slider see our king
+        let white = board.white_to_move;
+        let king_square = board.get_pieces(PieceType::King, white).trailing_zeros() as usize;
+        let enemies = board.pieces_of(!white);
+        let mut pinned = 0;
+        for square in 0..64 {
+            let piece = 1u64 << square;
+            if piece & board.pieces_of(white) & !board.get_pieces(PieceType::King, white) == 0 {
+                continue;
+            }
+            if board.attackers_to(king_square, board.occ & !piece) & enemies != board.attackers_to(king_square, board.occ) & enemies {
+                pinned |= piece;
+            }
+        }
+        assert_eq!(board.pinned(), pinned, "{}", board.to_fen());
+
+        for by_white in [true, false] {
+            let attacked = (0..64).filter(|&square| board.is_attacked(square, by_white)).fold(0u64, |attacked, square| attacked | 1 << square);
+            assert_eq!(board.attacks(by_white), attacked, "{}", board.to_fen());
+        }
+    }
+
+    #[test]
+    fn attack_queries_agree_with_checkmask_and_each_other() {
+        for fen in TEST_POSITIONS.into_iter().chain(CHECK_POSITIONS) {
+            for_each_position(&mut make_board(fen), 1, &mut |board| assert_attack_queries(board));
+        }
+    }
+}
@@ -15,12 +15,14 @@ enum PinDirection {
     Vertical,
     Horizontal,
     Diagonal,
+    /// Not a pin of a single piece, the en passant capture is illegal
+    EnPassant,
     NoPin
 }
 
 // square_between_straight: [[u64; 64]; 64] = generate_squares_between();
 pub fn get_checkmask(
-    board: &mut Board,
+    board: &Board,
     square_between_straight: &[[u64; 64]; 64],
     square_between_diag: &[[u64; 64]; 64]
 ) -> (u64, PinMask) {
@@ -47,10 +49,10 @@ pub fn get_checkmask(
     };
 
     if king_pos == 64 {
-        return (0xffffffffffffffff, PinMask { horizontal: 0u64, vertical: 0u64, diagonal: 0u64})
+        return (0xffffffffffffffff, PinMask { horizontal: 0u64, vertical: 0u64, diagonal: 0u64, en_passant_pinned: false })
     }
     let mut checkmask: u64 = KNIGHT_MOVES[king_pos as usize] & opp_pieces[3];
-    let mut pinmask: PinMask = PinMask { horizontal: 0u64, vertical: 0u64, diagonal: 0u64} ;
+    let mut pinmask: PinMask = PinMask { horizontal: 0u64, vertical: 0u64, diagonal: 0u64, en_passant_pinned: false };
 
     // Sliders
     for opp_slider in 0..3 {
@@ -61,6 +63,7 @@ pub fn get_checkmask(
                 PinDirection::Vertical => { pinmask.vertical |= slider_pin.1; }
                 PinDirection::Horizontal => { pinmask.horizontal |= slider_pin.1 }
                 PinDirection::Diagonal => { pinmask.diagonal |= slider_pin.1 }
+                PinDirection::EnPassant => { pinmask.en_passant_pinned = true }
                 _ => {}
             }
         }
@@ -83,7 +86,7 @@ pub fn get_checkmask(
 }
 
 fn generate_masks_sliding_pieces(
-    board: &mut Board,
+    board: &Board,
     square_between_straight: &[[u64; 64]; 64],
     square_between_diag: &[[u64; 64]; 64],
     king_pos: u32, mut opp_pieces: &mut [u64; 5],
@@ -141,7 +144,7 @@ fn generate_masks_sliding_pieces(
                     }
                     PinDirection::Diagonal => {
                         if (blockers & board.last_double_pawn_push) != 0 {
-                            board.last_double_pawn_push = 0;
+                            pindirection = PinDirection::EnPassant
                         } else {
                             pinmask |= slider_attack | (1 << piece_sq);
                             pindirection = PinDirection::Diagonal
@@ -155,7 +158,7 @@ fn generate_masks_sliding_pieces(
         2 => {
             let slider_path_contains_ep_pawn = slider_attack & board.last_double_pawn_push != 0;
             if slider_path_contains_ep_pawn && direction_mask == PinDirection::Straight && !same_file(king_pos as usize, piece_sq) {
-                board.last_double_pawn_push = 0;
+                pindirection = PinDirection::EnPassant
             };
         }
         _ => {}
@@ -1,6 +1,6 @@
 use crate::Board;
 use crate::helpers::pop_lsb;
-use crate::move_gen_dir::move_gen::{convert_bitboard_to_moves, GenerationMode, PieceType, PinMask};
+use crate::move_gen_dir::move_gen::{convert_bitboard_to_checking_moves, convert_bitboard_to_moves, ConvertFn, GenerationMode, PieceType, PinMask};
 use crate::move_list::MoveList;
 
 
@@ -23,21 +23,26 @@ pub fn generate_knight_moves(board: &Board, move_list: &mut MoveList, checkmask:
 
         match generation_mode {
             GenerationMode::All => {
-                generate_knight_silent(board, move_list, knight, possible_moves);
+                generate_knight_silent(board, move_list, knight, possible_moves, convert_bitboard_to_moves);
                 generate_knight_capture(board, move_list, opponent_pieces, knight, possible_moves);
             },
             GenerationMode::Capture => {
                 generate_knight_capture(board, move_list, opponent_pieces, knight, possible_moves);
             },
-            GenerationMode::Check => {}
+            GenerationMode::Quiet => {
+                generate_knight_silent(board, move_list, knight, possible_moves, convert_bitboard_to_moves);
+            },
+            GenerationMode::Check => {
+                generate_knight_silent(board, move_list, knight, possible_moves, convert_bitboard_to_checking_moves);
+            }
         }
     }
 }
 
-fn generate_knight_silent(board: &Board, move_list: &mut MoveList, knight: u64, possible_moves: u64) {
+fn generate_knight_silent(board: &Board, move_list: &mut MoveList, knight: u64, possible_moves: u64, convert: ConvertFn) {
     let no_capture_moves = possible_moves & !board.white & !board.black;
 
-    convert_bitboard_to_moves(board, move_list, 1 << knight, no_capture_moves, PieceType::Knight, PieceType::NoPiece);
+    convert(board, move_list, 1 << knight, no_capture_moves, PieceType::Knight, PieceType::NoPiece);
 }
 
 fn generate_knight_capture(board: &Board, move_list: &mut MoveList, opponent_pieces: u64, knight: u64, possible_moves: u64) {
@@ -6,4 +6,8 @@ pub mod king_move_gen;
 pub mod rook_table_const;
 pub mod bishop_table_const;
 pub mod move_gen;
-pub mod move_gen_tests;
\ No newline at end of file
+pub mod move_validation;
+pub mod attacks;
+#[cfg(test)]
+pub mod move_gen_tests;
+pub mod perft;
\ No newline at end of file
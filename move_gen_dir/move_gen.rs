@@ -2,7 +2,8 @@ use crate::helpers::pop_lsb;
 use crate::move_list::MoveList;
 use crate::Board;
 use crate::move_gen_dir::bishop_table_const::BISHOP_ATTACK_TABLE;
-use crate::move_gen_dir::precomputed_magics::{BISHOP_MAGICS, BISHOP_MASK, BISHOP_OFFSETS, BISHOP_SHIFTS, ROOK_MAGICS, ROOK_MASK, ROOK_OFFSETS, ROOK_SHIFTS};
+use crate::move_gen_dir::knight_move_gen::KNIGHT_MOVES;
+use crate::move_gen_dir::precomputed_magics::{BISHOP_MAGICS, BISHOP_MASK, BISHOP_OFFSETS, BISHOP_SHIFTS, PAWN_ATTACKS_BLACK, PAWN_ATTACKS_WHITE, ROOK_MAGICS, ROOK_MASK, ROOK_OFFSETS, ROOK_SHIFTS};
 use crate::move_gen_dir::rook_table_const::ROOK_ATTACK_TABLE;
 use strum_macros::{Display, EnumString};
 
@@ -17,6 +18,20 @@ pub struct Move {
     pub en_passant: bool,
 }
 
+impl Move {
+    /// Packs start square, end square and promotion into 16 bits, 0 is never a legal move.
+    pub const fn to_u16(&self) -> u16 {
+        let promotion = match self.promotion {
+            PieceType::Knight => 1,
+            PieceType::Bishop => 2,
+            PieceType::Rook => 3,
+            PieceType::Queen => 4,
+            _ => 0,
+        };
+        (self.start_square.trailing_zeros() as u16 & 0x3f) | ((self.end_square.trailing_zeros() as u16 & 0x3f) << 6) | (promotion << 12)
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Castling {
     KingSide,
@@ -52,25 +67,82 @@ pub struct PinMask {
     pub horizontal: u64,
     pub vertical: u64,
     pub diagonal: u64,
+    /// Taking en passant would remove both pawns from a line between our king and an enemy slider
+    pub en_passant_pinned: bool,
 }
 
 pub enum GenerationMode {
     All,
     Capture,
+    /// Everything `Capture` leaves out, so generating both gives the same moves as `All`
+    Quiet,
+    /// Non-capturing moves that give check, directly or by discovery, including promotions and castles
     Check
 }
 
 pub const KING_MOVES: [u64;64] = [770, 1797, 3594, 7188, 14376, 28752, 57504, 49216, 197123, 460039, 920078, 1840156, 3680312, 7360624, 14721248, 12599488, 50463488, 117769984, 235539968, 471079936, 942159872, 1884319744, 3768639488, 3225468928, 12918652928, 30149115904, 60298231808, 120596463616, 241192927232, 482385854464, 964771708928, 825720045568, 3307175149568, 7718173671424, 15436347342848, 30872694685696, 61745389371392, 123490778742784, 246981557485568, 211384331665408, 846636838289408, 1975852459884544, 3951704919769088, 7903409839538176, 15806819679076352, 31613639358152704, 63227278716305408, 54114388906344448, 216739030602088448, 505818229730443264, 1011636459460886528, 2023272918921773056, 4046545837843546112, 8093091675687092224, 16186183351374184448, 13853283560024178688, 144959613005987840, 362258295026614272, 724516590053228544, 1449033180106457088, 2898066360212914176, 5796132720425828352, 11592265440851656704, 4665729213955833856];
 
+/// Signature shared by `convert_bitboard_to_moves` and `convert_bitboard_to_checking_moves`.
+pub type ConvertFn = fn(&Board, &mut MoveList, u64, u64, PieceType, PieceType);
+
 pub fn convert_bitboard_to_moves(board: &Board, moves: &mut MoveList, start: u64, mut bitboard: u64, piece_type: PieceType, promotion: PieceType) {
     while bitboard != 0 {
         let destination = 1 << pop_lsb(&mut bitboard);
         let captured_piece = get_piece_from_square(board, destination);
-        moves.add_move(Move { start_square: start, end_square: destination, piece_type: piece_type, promotion: promotion , capture: captured_piece, castle: Castling::NoCastle, en_passant: false } );
+        moves.add_move(Move { start_square: start, end_square: destination, piece_type, promotion, capture: captured_piece, castle: Castling::NoCastle, en_passant: false } );
     }
 }
 
-const fn get_piece_from_square(board: &Board,square: u64) -> PieceType {
+/// Like `convert_bitboard_to_moves`, but only keeps the moves that give check.
+pub fn convert_bitboard_to_checking_moves(board: &Board, moves: &mut MoveList, start: u64, mut bitboard: u64, piece_type: PieceType, promotion: PieceType) {
+    while bitboard != 0 {
+        let destination = 1 << pop_lsb(&mut bitboard);
+        let captured_piece = get_piece_from_square(board, destination);
+        let mv = Move { start_square: start, end_square: destination, piece_type, promotion, capture: captured_piece, castle: Castling::NoCastle, en_passant: false };
+        if gives_check(board, &mv) {
+            moves.add_move(mv);
+        }
+    }
+}
+
+/// Whether the side to move attacks the enemy king after playing `mv`.
+pub fn gives_check(board: &Board, mv: &Move) -> bool {
+    let white = board.white_to_move;
+    let enemy_king = if white { board.bking } else { board.wking };
+    let king_square = enemy_king.trailing_zeros() as usize;
+
+    let (rook_start, rook_end) = match (white, mv.castle) {
+        (true, Castling::KingSide) => (0x80, 0x20),
+        (true, Castling::QueenSide) => (0x1, 0x8),
+        (false, Castling::KingSide) => (0x8000000000000000, 0x2000000000000000),
+        (false, Castling::QueenSide) => (0x100000000000000, 0x800000000000000),
+        (_, Castling::NoCastle) => (0, 0),
+    };
+    let en_passant_pawn = if mv.en_passant { board.last_double_pawn_push } else { 0 };
+    let occupancy = (board.occ & !mv.start_square & !en_passant_pawn & !rook_start) | mv.end_square | rook_end;
+
+    let piece_after_move = if mv.promotion != PieceType::NoPiece { mv.promotion } else { mv.piece_type };
+    let pieces_after_move = |piece_type: PieceType| {
+        let mut pieces = board.get_pieces(piece_type, white) & !mv.start_square;
+        if piece_type == piece_after_move {
+            pieces |= mv.end_square;
+        }
+        if piece_type == PieceType::Rook {
+            pieces = (pieces & !rook_start) | rook_end;
+        }
+        pieces
+    };
+
+    let pawn_checks = if white { PAWN_ATTACKS_BLACK[king_square] } else { PAWN_ATTACKS_WHITE[king_square] };
+    let queens = pieces_after_move(PieceType::Queen);
+
+    (pawn_checks & pieces_after_move(PieceType::Pawn)) != 0
+        || (KNIGHT_MOVES[king_square] & pieces_after_move(PieceType::Knight)) != 0
+        || (get_bishop_attacks(king_square, occupancy) & (pieces_after_move(PieceType::Bishop) | queens)) != 0
+        || (get_rook_attacks(king_square, occupancy) & (pieces_after_move(PieceType::Rook) | queens)) != 0
+}
+
+pub const fn get_piece_from_square(board: &Board,square: u64) -> PieceType {
     if !board.occ & square != 0 {
         return PieceType::NoPiece;
     }
@@ -128,7 +200,12 @@ fn generate_moves_filtered_by_mode(board: &Board, piece_type: PieceType, move_li
         GenerationMode::Capture => {
             convert_bitboard_to_moves(board, move_list, 1 << square, captures, piece_type, PieceType::NoPiece);
         },
-        GenerationMode::Check => {}
+        GenerationMode::Quiet => {
+            convert_bitboard_to_moves(board, move_list, 1 << square, quiets, piece_type, PieceType::NoPiece);
+        },
+        GenerationMode::Check => {
+            convert_bitboard_to_checking_moves(board, move_list, 1 << square, quiets, piece_type, PieceType::NoPiece);
+        }
     }
 }
 
@@ -173,7 +250,7 @@ pub const fn get_bishop_attacks(square: usize, occupancy: u64) -> u64 {
 }
 
 
-pub fn generate_queen_moves(board: &mut Board, move_list: &mut MoveList, checkmask: &u64, pin_mask: &PinMask, generation_mode: &GenerationMode) {
+pub fn generate_queen_moves(board: &Board, move_list: &mut MoveList, checkmask: &u64, pin_mask: &PinMask, generation_mode: &GenerationMode) {
     let quens = if board.white_to_move { board.wqueen } else { board.bqueen };
     generate_bishop_moves(board, quens, PieceType::Queen, move_list, checkmask, pin_mask, generation_mode);
     generate_rook_moves(board, quens, PieceType::Queen, move_list, checkmask, pin_mask, generation_mode);
@@ -1,66 +1,91 @@
 use crate::{generate_all_moves, Board};
-use crate::fen_import::make_board;
-use crate::helpers::index_to_sq;
 use crate::move_gen_dir::move_gen::GenerationMode;
 
-#[derive()]
-struct TestPosition {
-    fen: String,
-    depth: usize,
-    result: i64,
-}
-
+/// The standard perft positions, perft.epd has their reference counts
+pub const TEST_POSITIONS: [&str; 5] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+];
 
+/// Small positions where castling, promotions, king moves and minor pieces give check
+pub const CHECK_POSITIONS: [&str; 4] = [
+    "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+    "3k4/1P6/8/8/8/8/8/4K3 w - - 0 1",
+    "4k3/8/8/8/4K3/8/8/4R3 w - - 0 1",
+    "4k3/8/8/3N4/8/1B6/8/4K3 w - - 0 1",
+];
 
-pub fn _test_move_gen() {
-    let test_positions: [TestPosition;5] = [
-        TestPosition {fen: ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq").parse().unwrap(),depth:5,result:4865609},
-        TestPosition {fen: ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq").parse().unwrap(),depth:5,result:193690690},
-        TestPosition {fen: ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w -").parse().unwrap(),depth:7,result:178633661},
-        TestPosition {fen: ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq").parse().unwrap(),depth:6,result:706045033},
-        TestPosition {fen: ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ").parse().unwrap(),depth:5,result:89941194},
-    ];
-
-    let mut sum_postions = 0;
-    for position in test_positions.iter() {
-        let mut board = make_board(&position.fen);
-        let found_moves = _move_generation_test(position.depth as i32, &mut board, false);
-        println!("Found Moves after {}: {}", position.depth, found_moves);
-        println!("Actual Moves: {}", position.result);
-        println!("Difference: {}", (found_moves- position.result));
-        println!();
-        sum_postions += found_moves;
+/// Runs `check` on the board and on every position up to `depth` legal moves away from it,
+/// asserting along the way that undo_move restores each position.
+pub fn for_each_position(board: &mut Board, depth: usize, check: &mut impl FnMut(&mut Board)) {
+    check(board);
+    if depth == 0 {
+        return;
+    }
+    let fen = board.to_fen();
+    let move_list = generate_all_moves(board, &GenerationMode::All);
+    for i in 0..move_list.moves_added {
+        let last_mv_info = board.make_move(move_list.moves[i]);
+        for_each_position(board, depth - 1, check);
+        board.undo_move(last_mv_info);
+        assert_eq!(board.to_fen(), fen);
     }
-    println!("Final Sum: {}", sum_postions);
 }
 
-pub fn _move_generation_test(depth: i32, board: &mut Board, first_iteration: bool)-> i64 {
-    let mut positons: i64 = 0;
+#[cfg(test)]
+mod tests {
+    use super::TEST_POSITIONS;
+    use crate::fen_import::make_board;
+    use crate::move_gen_dir::perft::{divide, hashed_perft, parse_epd_line, perft, PerftTable};
 
-    let move_list = generate_all_moves(board, &GenerationMode::All);
+    // Shallow enough for debug builds, the full depths are in perft.epd
+    const SHALLOW_PERFT: [(usize, usize, u64); 5] = [(0, 4, 197281), (1, 3, 97862), (2, 4, 43238), (3, 3, 9467), (4, 3, 62379)];
 
-    if depth == 1 {
-        return move_list.moves_added as i64;
+    #[test]
+    fn perft_matches_reference_counts() {
+        for (position, depth, nodes) in SHALLOW_PERFT {
+            let mut board = make_board(TEST_POSITIONS[position]);
+            assert_eq!(perft(&mut board, depth), nodes, "{}", TEST_POSITIONS[position]);
+        }
     }
 
-    for moves in 0..move_list.moves_added {
-        let last_mv_info = board.make_move(move_list.moves[moves]);
+    #[test]
+    fn hashed_perft_and_divide_agree_with_perft() {
+        let mut table = PerftTable::new(1);
+        for (position, depth, nodes) in SHALLOW_PERFT {
+            let mut board = make_board(TEST_POSITIONS[position]);
+            assert_eq!(hashed_perft(&mut board, depth, &mut table), nodes, "{}", TEST_POSITIONS[position]);
+            assert_eq!(divide(&mut board, depth, &mut table).iter().map(|(_, nodes)| nodes).sum::<u64>(), nodes);
+        }
+    }
 
-        let positons_to_add = _move_generation_test(depth-1, board, false);
+    #[test]
+    fn parses_epd_perft_lines() {
+        let entry = parse_epd_line("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ;D1 14 ;D2 191").unwrap();
+        assert_eq!(entry.fen, "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -");
+        assert_eq!(entry.expected, vec![(1, 14), (2, 191)]);
+        assert!(parse_epd_line("8/8/8/8/8/8/8/8 w - - ;D1").is_err());
+        assert!(parse_epd_line("8/8/8/8/8/8/8/8 w - - ;X1 3").is_err());
+    }
 
-        if first_iteration {
-            println!("{:?}{:?}: {:?}",
-                     index_to_sq(move_list.moves[moves].start_square.trailing_zeros() as usize),
-                     index_to_sq(move_list.moves[moves].end_square.trailing_zeros() as usize),
-                     positons_to_add,
-                     // move_list.moves[moves].piece_type,
-                     );
-        }
+    #[test]
+    fn null_move_flips_side_and_restores_everything() {
+        for fen in TEST_POSITIONS.into_iter().chain(["4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2"]) {
+            let mut board = make_board(fen);
+            let before = board.clone();
 
-        positons += positons_to_add;
+            let last_mv_info = board.make_null_move();
+            assert_eq!(board.white_to_move, !before.white_to_move);
+            assert_eq!(board.last_double_pawn_push, 0);
+            assert_eq!(board.zobrist_hash(), board.compute_zobrist_hash());
+            assert_ne!(board.zobrist_hash(), before.zobrist_hash());
 
-        board.undo_move(last_mv_info);
+            board.undo_null_move(last_mv_info);
+            assert_eq!(board.to_fen(), before.to_fen());
+            assert_eq!(board.zobrist_hash(), before.zobrist_hash());
+        }
     }
-
-    positons
-}
\ No newline at end of file
+}
@@ -0,0 +1,131 @@
+use crate::Board;
+use crate::move_gen_dir::knight_move_gen::KNIGHT_MOVES;
+use crate::move_gen_dir::move_gen::{get_bishop_attacks, get_piece_from_square, get_rook_attacks, Castling, Move, KING_MOVES};
+use crate::move_gen_dir::move_gen::PieceType::{Bishop, King, Knight, NoPiece, Pawn, Queen, Rook};
+
+impl Board {
+    /// Turns a move packed with `Move::to_u16` back into a move, if it is legal here. The move is checked
+    /// against the position directly, so TT moves and killers can be tried before anything is generated.
+    pub fn decode_move(&self, packed_move: u16) -> Option<Move> {
+        let from = (packed_move & 0x3f) as usize;
+        let to = ((packed_move >> 6) & 0x3f) as usize;
+        let promotion = match packed_move >> 12 {
+            0 => NoPiece,
+            1 => Knight,
+            2 => Bishop,
+            3 => Rook,
+            4 => Queen,
+            _ => return None,
+        };
+        let (start_square, end_square) = (1u64 << from, 1u64 << to);
+        let white = self.white_to_move;
+        let (friendly_pieces, enemy_pieces) = if white { (self.white, self.black) } else { (self.black, self.white) };
+        if start_square & friendly_pieces == 0 || end_square & friendly_pieces != 0 {
+            return None;
+        }
+
+        let piece_type = get_piece_from_square(self, start_square);
+        let mut mv = Move { start_square, end_square, capture: get_piece_from_square(self, end_square), piece_type, promotion, castle: Castling::NoCastle, en_passant: false };
+        if mv.capture == King {
+            return None;
+        }
+        let promotion_rank = if white { 0xff00000000000000 } else { 0xff };
+        if (piece_type == Pawn && end_square & promotion_rank != 0) != (promotion != NoPiece) {
+            return None;
+        }
+
+        let reachable = match piece_type {
+            Pawn => {
+                // The pawn attack tables don't cover pawns on their own second rank, so shift instead
+                let (single_push, double_push, attacks) = if white {
+                    let single_push = (start_square << 8) & !self.occ;
+                    let attacks = ((start_square & 0xfefefefefefefefe) << 7) | ((start_square & 0x7f7f7f7f7f7f7f7f) << 9);
+                    (single_push, ((start_square & 0xff00) << 16) & !self.occ & (single_push << 8), attacks)
+                } else {
+                    let single_push = (start_square >> 8) & !self.occ;
+                    let attacks = ((start_square & 0x7f7f7f7f7f7f7f7f) >> 7) | ((start_square & 0xfefefefefefefefe) >> 9);
+                    (single_push, ((start_square & 0xff000000000000) >> 16) & !self.occ & (single_push >> 8), attacks)
+                };
+                let en_passant_target = if white { self.last_double_pawn_push << 8 } else { self.last_double_pawn_push >> 8 };
+                if end_square & attacks & en_passant_target != 0 {
+                    mv.capture = Pawn;
+                    mv.en_passant = true;
+                }
+                single_push | double_push | (attacks & (enemy_pieces | en_passant_target))
+            }
+            Knight => KNIGHT_MOVES[from],
+            Bishop => get_bishop_attacks(from, self.occ),
+            Rook => get_rook_attacks(from, self.occ),
+            Queen => get_bishop_attacks(from, self.occ) | get_rook_attacks(from, self.occ),
+            King if from.abs_diff(to) == 2 => return self.decode_castling(mv),
+            King => KING_MOVES[from],
+            NoPiece => 0,
+        };
+        if reachable & end_square == 0 {
+            return None;
+        }
+
+        // The move must not leave our king attacked, captured pieces no longer attack anything
+        let captured = if mv.en_passant { self.last_double_pawn_push } else { end_square };
+        let occupancy = (self.occ & !start_square & !captured) | end_square;
+        let king_square = if piece_type == King { to } else { self.get_pieces(King, white).trailing_zeros() as usize };
+        if self.attackers_to(king_square, occupancy) & enemy_pieces & !captured != 0 {
+            return None;
+        }
+        Some(mv)
+    }
+
+    fn decode_castling(&self, mut mv: Move) -> Option<Move> {
+        let white = self.white_to_move;
+        let (correction_shift, right_correction_shift) = if white { (0, 0) } else { (56, 2) };
+        let (castle, right, empty_squares, king_path) = match mv.end_square >> correction_shift {
+            0x40 => (Castling::KingSide, 0b0001, 0x60, [4, 5, 6]),
+            0x4 => (Castling::QueenSide, 0b0010, 0xe, [4, 3, 2]),
+            _ => return None,
+        };
+        if mv.start_square != 0x10 << correction_shift || self.castling_rights & (right << right_correction_shift) == 0 || self.occ & (empty_squares << correction_shift) != 0 {
+            return None;
+        }
+        if king_path.iter().any(|square| self.is_attacked(square + correction_shift, !white)) {
+            return None;
+        }
+        mv.castle = castle;
+        Some(mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fen_import::make_board;
+    use crate::move_gen_dir::move_gen::GenerationMode;
+    use crate::move_gen_dir::move_gen_tests::{for_each_position, CHECK_POSITIONS, TEST_POSITIONS};
+    use crate::move_list::MoveList;
+    use crate::{append_moves, generate_all_moves};
+
+    fn sorted_moves(move_list: &MoveList) -> Vec<(u16, String)> {
+        let mut moves: Vec<(u16, String)> = move_list.moves[..move_list.moves_added].iter().map(|mv| (mv.to_u16(), format!("{:?}", mv))).collect();
+        moves.sort();
+        moves
+    }
+
+    #[test]
+    fn capture_and_quiet_modes_split_all_moves_and_decode_move_agrees() {
+        let en_passant_pin = "8/8/8/KPp4r/8/8/8/6k1 w - c6 0 2";
+        for fen in TEST_POSITIONS.into_iter().chain(CHECK_POSITIONS).chain([en_passant_pin]) {
+            for_each_position(&mut make_board(fen), 1, &mut |board| {
+                let all_moves = generate_all_moves(board, &GenerationMode::All);
+                let mut split = generate_all_moves(board, &GenerationMode::Capture);
+                append_moves(board, &mut split, &GenerationMode::Quiet);
+                assert_eq!(sorted_moves(&split), sorted_moves(&all_moves), "{}", board.to_fen());
+
+                let mut decoded = MoveList::new();
+                for packed_move in 0..0x5000 {
+                    if let Some(mv) = board.decode_move(packed_move) {
+                        decoded.add_move(mv);
+                    }
+                }
+                assert_eq!(sorted_moves(&decoded), sorted_moves(&all_moves), "{}", board.to_fen());
+            });
+        }
+    }
+}
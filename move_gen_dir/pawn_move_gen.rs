@@ -1,6 +1,6 @@
 use crate::Board;
 use crate::helpers::pop_lsb;
-use crate::move_gen_dir::move_gen::{convert_bitboard_to_moves, GenerationMode, Move, PieceType, PinMask};
+use crate::move_gen_dir::move_gen::{convert_bitboard_to_checking_moves, convert_bitboard_to_moves, ConvertFn, GenerationMode, Move, PieceType, PinMask};
 use crate::move_gen_dir::move_gen::Castling::NoCastle;
 use crate::move_gen_dir::move_gen::PieceType::NoPiece;
 use crate::move_list::MoveList;
@@ -17,10 +17,26 @@ const fn shift_left(position: u64, shift: u32) ->u64 {
 const fn shift_right(position: u64, shift: u32) ->u64 {
     position.wrapping_shr(shift)
 }
+/// What the pawn generators need to know about the side to move, whose pawns move up or down the board.
+struct PawnContext<'a> {
+    board: &'a Board,
+    checkmask: u64,
+    en_passant_pinned: bool,
+    opponent: u64,
+    push: fn(u64) -> u64,
+    double_push: fn(u64) -> u64,
+    capture_left: fn(u64) -> u64,
+    capture_right: fn(u64) -> u64,
+    first_rank: u64,
+    promotion_rank: u64,
+    /// From the en passant pawn to the square behind it
+    shift_back: fn(u64) -> u64,
+}
+
 pub fn generate_pawn_moves(board: &Board, move_list: &mut MoveList, checkmask: &u64, pin_mask: &PinMask, generation_mode: &GenerationMode) {
-    let (pawns, opponent, push_fn, double_push_fn, left_capture_fn, right_capture_fn, first_rank, promotion_rank, shift_back) = pawn_info(board);
+    let context = pawn_context(board, *checkmask, pin_mask.en_passant_pinned);
 
-    let mut remaining = pawns;
+    let mut remaining = if board.white_to_move { board.wpawn } else { board.bpawn };
     while remaining != 0 {
         let from_sq = pop_lsb(&mut remaining);
         let pawn = 1u64 << from_sq;
@@ -38,49 +54,56 @@ pub fn generate_pawn_moves(board: &Board, move_list: &mut MoveList, checkmask: &
 
         match generation_mode {
             GenerationMode::All => {
-                generate_pawn_silent(board, move_list, checkmask, push_fn, double_push_fn, first_rank, promotion_rank, pawn, pawn_pinmask);
-                generate_pawn_captures(board, move_list, checkmask, opponent, left_capture_fn, right_capture_fn, promotion_rank, shift_back, pawn, pawn_pinmask);
+                generate_pawn_silent(&context, move_list, pawn, pawn_pinmask, convert_bitboard_to_moves);
+                generate_pawn_captures(&context, move_list, pawn, pawn_pinmask);
             },
             GenerationMode::Capture => {
-                generate_pawn_captures(board, move_list, checkmask, opponent, left_capture_fn, right_capture_fn, promotion_rank, shift_back, pawn, pawn_pinmask);
+                generate_pawn_captures(&context, move_list, pawn, pawn_pinmask);
+            },
+            GenerationMode::Quiet => {
+                generate_pawn_silent(&context, move_list, pawn, pawn_pinmask, convert_bitboard_to_moves);
             },
-            GenerationMode::Check => {}
+            GenerationMode::Check => {
+                generate_pawn_silent(&context, move_list, pawn, pawn_pinmask, convert_bitboard_to_checking_moves);
+            }
         }
     }
 }
 
-fn generate_pawn_silent(board: &Board, move_list: &mut MoveList, checkmask: &u64, push_fn: fn(u64) -> u64, double_push_fn: fn(u64) -> u64, first_rank: u64, promotion_rank: u64, pawn: u64, pawn_pinmask: u64) {
+fn generate_pawn_silent(context: &PawnContext, move_list: &mut MoveList, pawn: u64, pawn_pinmask: u64, convert: ConvertFn) {
+    let board = context.board;
     // Quiet moves
-    let single_push = push_fn(pawn) & !board.occ;
+    let single_push = (context.push)(pawn) & !board.occ;
 
-    let double_push = double_push_fn(pawn & first_rank)
+    let double_push = (context.double_push)(pawn & context.first_rank)
         & !board.occ
-        & push_fn(single_push);
+        & (context.push)(single_push);
 
 
-    let mut quiet_moves = (single_push | double_push) & checkmask & pawn_pinmask;
+    let mut quiet_moves = (single_push | double_push) & context.checkmask & pawn_pinmask;
 
 
-    let promotions = quiet_moves & promotion_rank;
-    quiet_moves &= !promotion_rank;
+    let promotions = quiet_moves & context.promotion_rank;
+    quiet_moves &= !context.promotion_rank;
 
-    convert_bitboard_to_moves(board, move_list, pawn, quiet_moves, PieceType::Pawn, NoPiece);
+    convert(board, move_list, pawn, quiet_moves, PieceType::Pawn, NoPiece);
 
     for promo in PROMOTION_OPTIONS {
-        convert_bitboard_to_moves(board, move_list, pawn, promotions, PieceType::Pawn, promo);
+        convert(board, move_list, pawn, promotions, PieceType::Pawn, promo);
     }
 }
 
-fn generate_pawn_captures(board: &Board, move_list: &mut MoveList, checkmask: &u64, opponent: u64, left_capture_fn: fn(u64) -> u64, right_capture_fn: fn(u64) -> u64, promotion_rank: u64, shift_back: fn(u64) -> u64, pawn: u64, pawn_pinmask: u64) {
+fn generate_pawn_captures(context: &PawnContext, move_list: &mut MoveList, pawn: u64, pawn_pinmask: u64) {
+    let board = context.board;
     // Captures
-    let capture_left = left_capture_fn(pawn) & opponent;
-    let capture_right = right_capture_fn(pawn) & opponent;
+    let capture_left = (context.capture_left)(pawn) & context.opponent;
+    let capture_right = (context.capture_right)(pawn) & context.opponent;
 
 
-    let mut captures = (capture_left | capture_right) & checkmask & pawn_pinmask;
+    let mut captures = (capture_left | capture_right) & context.checkmask & pawn_pinmask;
 
-    let promo_caps = captures & promotion_rank;
-    captures &= !promotion_rank;
+    let promo_caps = captures & context.promotion_rank;
+    captures &= !context.promotion_rank;
 
     convert_bitboard_to_moves(board, move_list, pawn, captures, PieceType::Pawn, NoPiece);
 
@@ -88,17 +111,17 @@ fn generate_pawn_captures(board: &Board, move_list: &mut MoveList, checkmask: &u
         convert_bitboard_to_moves(board, move_list, pawn, promo_caps, PieceType::Pawn, promo);
     }
 
-    let ep_target = board.last_double_pawn_push;
-    let ep_pawn_target = shift_back(ep_target);
+    let ep_target = if context.en_passant_pinned { 0 } else { board.last_double_pawn_push };
+    let ep_pawn_target = (context.shift_back)(ep_target);
 
-    let left_ep = left_capture_fn(pawn) & ep_pawn_target;
-    let right_ep = right_capture_fn(pawn) & ep_pawn_target;
+    let left_ep = (context.capture_left)(pawn) & ep_pawn_target;
+    let right_ep = (context.capture_right)(pawn) & ep_pawn_target;
 
-    let ep_check = if checkmask & board.last_double_pawn_push != 0 {
+    let ep_check = if context.checkmask & board.last_double_pawn_push != 0 {
         ep_pawn_target
     } else { 0 };
 
-    let mut ep_captures = (left_ep | right_ep) & (checkmask | ep_check) & pawn_pinmask;
+    let mut ep_captures = (left_ep | right_ep) & (context.checkmask | ep_check) & pawn_pinmask;
 
     while ep_captures != 0 {
         let to_sq = pop_lsb(&mut ep_captures);
@@ -114,30 +137,34 @@ fn generate_pawn_captures(board: &Board, move_list: &mut MoveList, checkmask: &u
     }
 }
 
-const fn pawn_info(board: &Board) -> (u64, u64, fn(u64) -> u64, fn(u64) -> u64, fn(u64) -> u64, fn(u64) -> u64, u64, u64, fn(u64) -> u64) {
+const fn pawn_context(board: &Board, checkmask: u64, en_passant_pinned: bool) -> PawnContext<'_> {
     if board.white_to_move {
-        (
-            board.wpawn,
-            board.black,
-            |x: u64| shift_left(x, 8),
-            |x: u64| shift_left(x, 16),
-            |x: u64| shift_left(x & 0xfefefefefefefefe, 7), // capture left (A file masked)
-            |x: u64| shift_left(x & 0x7f7f7f7f7f7f7f7f, 9), // capture right (H file masked)
-            0x000000000000FF00,
-            0xFF00000000000000,
-            |x: u64| shift_left(x, 8),
-        )
+        PawnContext {
+            board,
+            checkmask,
+            en_passant_pinned,
+            opponent: board.black,
+            push: |x: u64| shift_left(x, 8),
+            double_push: |x: u64| shift_left(x, 16),
+            capture_left: |x: u64| shift_left(x & 0xfefefefefefefefe, 7), // capture left (A file masked)
+            capture_right: |x: u64| shift_left(x & 0x7f7f7f7f7f7f7f7f, 9), // capture right (H file masked)
+            first_rank: 0x000000000000FF00,
+            promotion_rank: 0xFF00000000000000,
+            shift_back: |x: u64| shift_left(x, 8),
+        }
     } else {
-        (
-            board.bpawn,
-            board.white,
-            |x: u64| shift_right(x, 8),
-            |x: u64| shift_right(x, 16),
-            |x: u64| shift_right(x & 0x7f7f7f7f7f7f7f7f, 7), // capture left (H file masked, mirrored)
-            |x: u64| shift_right(x & 0xfefefefefefefefe, 9), // capture right (A file masked, mirrored)
-            0x00FF000000000000,
-            0x00000000000000FF,
-            |x: u64| shift_right(x, 8),
-        )
+        PawnContext {
+            board,
+            checkmask,
+            en_passant_pinned,
+            opponent: board.white,
+            push: |x: u64| shift_right(x, 8),
+            double_push: |x: u64| shift_right(x, 16),
+            capture_left: |x: u64| shift_right(x & 0x7f7f7f7f7f7f7f7f, 7), // capture left (H file masked, mirrored)
+            capture_right: |x: u64| shift_right(x & 0xfefefefefefefefe, 9), // capture right (A file masked, mirrored)
+            first_rank: 0x00FF000000000000,
+            promotion_rank: 0x00000000000000FF,
+            shift_back: |x: u64| shift_right(x, 8),
+        }
     }
 }
@@ -0,0 +1,211 @@
+use crate::move_gen_dir::move_gen::{GenerationMode, Move};
+use crate::uci::convert_mv_to_uci;
+use crate::{generate_all_moves, Board};
+use std::fs;
+use std::time::Instant;
+
+const DEFAULT_PERFT_HASH_MB: usize = 64;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PerftEntry {
+    key: u64,
+    depth: usize,
+    nodes: u64,
+}
+
+/// Caches subtree node counts by Zobrist key and depth, always replacing.
+pub struct PerftTable {
+    entries: Vec<PerftEntry>,
+}
+
+impl PerftTable {
+    pub fn new(size_mb: usize) -> Self {
+        let num_entries = (size_mb * 1024 * 1024 / size_of::<PerftEntry>()).max(1);
+        PerftTable { entries: vec![PerftEntry::default(); num_entries] }
+    }
+
+    fn index(&self, zobrist_key: u64) -> usize {
+        (zobrist_key % self.entries.len() as u64) as usize
+    }
+
+    fn probe(&self, zobrist_key: u64, depth: usize) -> Option<u64> {
+        let entry = &self.entries[self.index(zobrist_key)];
+        if entry.key == zobrist_key && entry.depth == depth {
+            Some(entry.nodes)
+        } else {
+            None
+        }
+    }
+
+    fn store(&mut self, zobrist_key: u64, depth: usize, nodes: u64) {
+        let index = self.index(zobrist_key);
+        self.entries[index] = PerftEntry { key: zobrist_key, depth, nodes };
+    }
+}
+
+/// Number of leaf nodes `depth` plies below `board`, the last ply is counted without making the moves.
+pub fn perft(board: &mut Board, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let move_list = generate_all_moves(board, &GenerationMode::All);
+    if depth == 1 {
+        return move_list.moves_added as u64;
+    }
+
+    let mut nodes = 0;
+    for i in 0..move_list.moves_added {
+        let last_mv_info = board.make_move(move_list.moves[i]);
+        nodes += perft(board, depth - 1);
+        board.undo_move(last_mv_info);
+    }
+    nodes
+}
+
+pub fn hashed_perft(board: &mut Board, depth: usize, table: &mut PerftTable) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let zobrist_key = board.zobrist_hash();
+    let move_list = generate_all_moves(board, &GenerationMode::All);
+    if depth == 1 {
+        return move_list.moves_added as u64;
+    }
+    if let Some(nodes) = table.probe(zobrist_key, depth) {
+        return nodes;
+    }
+
+    let mut nodes = 0;
+    for i in 0..move_list.moves_added {
+        let last_mv_info = board.make_move(move_list.moves[i]);
+        nodes += hashed_perft(board, depth - 1, table);
+        board.undo_move(last_mv_info);
+    }
+    table.store(zobrist_key, depth, nodes);
+    nodes
+}
+
+/// Perft split by root move.
+pub fn divide(board: &mut Board, depth: usize, table: &mut PerftTable) -> Vec<(Move, u64)> {
+    let move_list = generate_all_moves(board, &GenerationMode::All);
+    let mut counts = Vec::with_capacity(move_list.moves_added);
+
+    for i in 0..move_list.moves_added {
+        let last_mv_info = board.make_move(move_list.moves[i]);
+        let nodes = hashed_perft(board, depth.saturating_sub(1), table);
+        board.undo_move(last_mv_info);
+        counts.push((move_list.moves[i], nodes));
+    }
+    counts
+}
+
+/// Prints the divide in the format Stockfish uses, so the output can be diffed against it.
+pub fn print_divide(board: &mut Board, depth: usize) {
+    let mut table = PerftTable::new(DEFAULT_PERFT_HASH_MB);
+    let counts = divide(board, depth.max(1), &mut table);
+
+    for (mv, nodes) in counts.iter() {
+        println!("{}: {}", convert_mv_to_uci(*mv), nodes);
+    }
+    println!();
+    println!("Nodes searched: {}", counts.iter().map(|(_, nodes)| nodes).sum::<u64>());
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EpdEntry {
+    pub fen: String,
+    pub expected: Vec<(usize, u64)>,
+}
+
+/// Parses `<fen> ;D1 20 ;D2 400 ...`, the FEN may leave out the move counters.
+pub fn parse_epd_line(line: &str) -> Result<EpdEntry, String> {
+    let mut parts = line.split(';');
+    let fen = parts.next().unwrap_or("").trim().to_string();
+    let mut expected = Vec::new();
+
+    for part in parts {
+        let mut tokens = part.split_whitespace();
+        let (depth, nodes) = match (tokens.next(), tokens.next()) {
+            (Some(depth), Some(nodes)) => (depth, nodes),
+            _ => return Err(format!("malformed perft entry \"{}\"", part.trim())),
+        };
+        let depth = depth.strip_prefix('D').and_then(|depth| depth.parse::<usize>().ok());
+        match (depth, nodes.parse::<u64>()) {
+            (Some(depth), Ok(nodes)) => expected.push((depth, nodes)),
+            _ => return Err(format!("malformed perft entry \"{}\"", part.trim())),
+        }
+    }
+    Ok(EpdEntry { fen, expected })
+}
+
+/// `perft <file.epd> [--depth N] [--hash MB]`, checks every `;Dn` expectation up to the given depth.
+/// Returns whether all of them matched.
+pub fn perft_cli(args: &[String]) -> bool {
+    let mut path = None;
+    let mut max_depth = usize::MAX;
+    let mut hash_mb = DEFAULT_PERFT_HASH_MB;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => max_depth = args.next().and_then(|depth| depth.parse().ok()).unwrap_or(max_depth),
+            "--hash" => hash_mb = args.next().and_then(|hash| hash.parse().ok()).unwrap_or(hash_mb),
+            _ => path = Some(arg),
+        }
+    }
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: perft <file.epd> [--depth N] [--hash MB]");
+            return false;
+        }
+    };
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            eprintln!("couldn't read {}: {}", path, err);
+            return false;
+        }
+    };
+
+    let mut table = if hash_mb > 0 { Some(PerftTable::new(hash_mb)) } else { None };
+    let mut failures = 0;
+    let now = Instant::now();
+
+    for (line_number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = match parse_epd_line(line) {
+            Ok(entry) => entry,
+            Err(err) => {
+                eprintln!("line {}: {}", line_number + 1, err);
+                failures += 1;
+                continue;
+            }
+        };
+        let mut board = match Board::from_fen(&entry.fen) {
+            Ok(board) => board,
+            Err(err) => {
+                eprintln!("line {}: {}", line_number + 1, err);
+                failures += 1;
+                continue;
+            }
+        };
+
+        for &(depth, expected) in entry.expected.iter().filter(|(depth, _)| *depth <= max_depth) {
+            let nodes = match table.as_mut() {
+                Some(table) => hashed_perft(&mut board, depth, table),
+                None => perft(&mut board, depth),
+            };
+            let status = if nodes == expected { "ok" } else { "FAIL" };
+            if nodes != expected {
+                failures += 1;
+            }
+            println!("{:<4} D{} {:>12} {:>12}  {}", status, depth, nodes, expected, entry.fen);
+        }
+    }
+
+    println!("{} failures, {:.2?}", failures, now.elapsed());
+    failures == 0
+}
@@ -0,0 +1,614 @@
+use crate::fen_import::{start_pos, FenError};
+use crate::move_gen_dir::move_gen::Move;
+use crate::san::SanError;
+use crate::search::pv_table::MAX_PLY;
+use crate::search::search::{Searcher, MATE_VALUE};
+use crate::search::time_manager::SearchLimits;
+use crate::{Board, MoveInfo};
+use std::fmt;
+use std::fs;
+
+const MAX_LINE_LENGTH: usize = 80;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+    Unknown,
+}
+
+impl GameResult {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        }
+    }
+
+    pub fn parse(token: &str) -> Option<GameResult> {
+        match token {
+            "1-0" => Some(GameResult::WhiteWin),
+            "0-1" => Some(GameResult::BlackWin),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// Everything written after a move in the main line.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Annotation {
+    pub nags: Vec<u8>,
+    /// Comment text without the `[%eval]` command
+    pub comment: Option<String>,
+    /// Evaluation from White's point of view, in centipawns or as a `MATE_VALUE` based mate score
+    pub eval: Option<i32>,
+}
+
+/// A line played instead of one of the moves of the line it is attached to.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Variation {
+    /// Ply of the game, counted from 0, at which the first move of the variation replaces the one of the enclosing line
+    pub ply: usize,
+    pub moves: Vec<Move>,
+    /// Variations of this line, branching off at plies after `ply`
+    pub variations: Vec<Variation>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub start_position: Board,
+    pub moves: Vec<Move>,
+    /// One entry per move in `moves`
+    pub annotations: Vec<Annotation>,
+    /// Variations of the main line, in the order they were written
+    pub variations: Vec<Variation>,
+    pub result: GameResult,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PgnError {
+    Io(String),
+    InvalidTag(String),
+    InvalidFen(FenError),
+    IllegalMove { game: usize, ply: usize, error: SanError },
+    UnterminatedComment,
+    UnbalancedVariation,
+    VariationWithoutMove,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::Io(err) => write!(f, "{}", err),
+            PgnError::InvalidTag(tag) => write!(f, "invalid tag pair {}", tag),
+            PgnError::InvalidFen(err) => write!(f, "invalid FEN tag: {}", err),
+            PgnError::IllegalMove { game, ply, error } => write!(f, "game {}, ply {}: {}", game, ply, error),
+            PgnError::UnterminatedComment => write!(f, "comment without closing brace"),
+            PgnError::UnbalancedVariation => write!(f, "unbalanced parentheses in variation"),
+            PgnError::VariationWithoutMove => write!(f, "variation before the first move of its line"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+impl Game {
+    pub fn new(start_position: Board) -> Self {
+        Game { tags: Vec::new(), start_position, moves: Vec::new(), annotations: Vec::new(), variations: Vec::new(), result: GameResult::Unknown }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn push_move(&mut self, mv: Move, eval: Option<i32>) {
+        self.moves.push(mv);
+        self.annotations.push(Annotation { eval, ..Default::default() });
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut pgn = String::new();
+        for (name, value) in self.tags.iter() {
+            if name != "Result" {
+                pgn.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+            }
+        }
+        pgn.push_str(&format!("[Result \"{}\"]\n\n", self.result.as_str()));
+
+        let mut tokens = Vec::new();
+        write_line(&mut tokens, self.start_position.clone(), 0, &self.moves, &self.annotations, &self.variations);
+        tokens.push(self.result.as_str().to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+            line_length += token.len();
+            pgn.push_str(&token);
+        }
+        pgn.push_str("\n\n");
+        pgn
+    }
+}
+
+/// Appends the movetext tokens of a line starting at `ply` from `board`. Variations follow the move they replace,
+/// each wrapped in parentheses. `annotations` may be shorter than `moves`, variations are written without them.
+fn write_line(tokens: &mut Vec<String>, mut board: Board, ply: usize, moves: &[Move], annotations: &[Annotation], variations: &[Variation]) {
+    let mut needs_move_number = true;
+
+    for (index, mv) in moves.iter().enumerate() {
+        if board.white_to_move {
+            tokens.push(format!("{}.", board.fullmove_number));
+        } else if needs_move_number {
+            tokens.push(format!("{}...", board.fullmove_number));
+        }
+        tokens.push(board.move_to_san(*mv));
+        let last_mv_info = board.make_move(*mv);
+        needs_move_number = false;
+
+        if let Some(annotation) = annotations.get(index) {
+            tokens.extend(annotation.nags.iter().map(|nag| format!("${}", nag)));
+            let comment = match (annotation.eval, &annotation.comment) {
+                (Some(eval), Some(comment)) => Some(format!("{{[%eval {}] {}}}", format_eval(eval), comment)),
+                (Some(eval), None) => Some(format!("{{[%eval {}]}}", format_eval(eval))),
+                (None, Some(comment)) => Some(format!("{{{}}}", comment)),
+                (None, None) => None,
+            };
+            needs_move_number = comment.is_some();
+            tokens.extend(comment);
+        }
+
+        let branches: Vec<&Variation> = variations.iter().filter(|variation| variation.ply == ply + index && !variation.moves.is_empty()).collect();
+        if !branches.is_empty() {
+            let mut branch_board = board.clone();
+            branch_board.undo_move(last_mv_info);
+            for variation in branches {
+                let first = tokens.len();
+                write_line(tokens, branch_board.clone(), variation.ply, &variation.moves, &[], &variation.variations);
+                tokens[first].insert(0, '(');
+                tokens.last_mut().unwrap().push(')');
+            }
+            needs_move_number = true;
+        }
+    }
+}
+
+/// `[%eval]` value: pawns with two decimals, or `#N` for mates.
+fn format_eval(eval: i32) -> String {
+    if eval.abs() > MATE_VALUE - MAX_PLY as i32 {
+        let moves_to_mate = (MATE_VALUE - eval.abs() + 1) / 2;
+        format!("#{}", if eval > 0 { moves_to_mate } else { -moves_to_mate })
+    } else {
+        format!("{:.2}", eval as f64 / 100.0)
+    }
+}
+
+fn parse_eval(value: &str) -> Option<i32> {
+    match value.strip_prefix('#') {
+        Some(mate) => {
+            let moves = mate.parse::<i32>().ok()?;
+            let plies = (2 * moves.abs() - 1).max(0);
+            Some(if moves > 0 { MATE_VALUE - plies } else { -MATE_VALUE + plies })
+        }
+        None => value.parse::<f64>().ok().map(|pawns| (pawns * 100.0).round() as i32),
+    }
+}
+
+/// Splits the `[%eval]` command out of a comment.
+fn parse_comment(text: &str) -> (Option<String>, Option<i32>) {
+    let mut eval = None;
+    let mut rest = text.to_string();
+    if let Some(start) = text.find("[%eval") {
+        if let Some(length) = text[start..].find(']') {
+            eval = text[start + 6..start + length].split_whitespace().next().and_then(parse_eval);
+            rest = format!("{}{}", &text[..start], &text[start + length + 1..]);
+        }
+    }
+    let rest = rest.trim();
+    (if rest.is_empty() { None } else { Some(rest.to_string()) }, eval)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+    San(String),
+}
+
+fn parse_tag(text: &str) -> Result<Token, PgnError> {
+    let invalid = || PgnError::InvalidTag(format!("[{}]", text));
+    let text = text.trim();
+    let name_end = text.find(char::is_whitespace).ok_or_else(invalid)?;
+    let value = text[name_end..].trim();
+    if value.len() < 2 || !value.starts_with('"') || !value.ends_with('"') {
+        return Err(invalid());
+    }
+    let value = value[1..value.len() - 1].replace("\\\"", "\"").replace("\\\\", "\\");
+    Ok(Token::Tag(text[..name_end].to_string(), value))
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(character) = chars.next() {
+        match character {
+            // Escaped lines are for other programs
+            '%' if line_start => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            ';' => {
+                let comment: String = std::iter::from_fn(|| chars.next_if(|&c| c != '\n')).collect();
+                tokens.push(Token::Comment(comment));
+            }
+            '{' => {
+                let comment: String = std::iter::from_fn(|| chars.next_if(|&c| c != '}')).collect();
+                if chars.next().is_none() {
+                    return Err(PgnError::UnterminatedComment);
+                }
+                tokens.push(Token::Comment(comment));
+            }
+            '[' => {
+                let mut tag = String::new();
+                let mut in_string = false;
+                loop {
+                    match chars.next() {
+                        Some('\\') if in_string => {
+                            tag.push('\\');
+                            tag.extend(chars.next());
+                        }
+                        Some('"') => {
+                            in_string = !in_string;
+                            tag.push('"');
+                        }
+                        Some(']') if !in_string => break,
+                        Some(c) => tag.push(c),
+                        None => return Err(PgnError::InvalidTag(format!("[{}", tag))),
+                    }
+                }
+                tokens.push(parse_tag(&tag)?);
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => {
+                let number: String = std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_digit())).collect();
+                tokens.extend(number.parse::<u8>().ok().map(Token::Nag));
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+                word.extend(std::iter::from_fn(|| chars.next_if(|&c| !c.is_whitespace() && !"{}()[];$".contains(c))));
+                tokens.extend(word_tokens(&word));
+            }
+        }
+        line_start = character == '\n';
+    }
+    Ok(tokens)
+}
+
+/// A movetext word is a result, a move number like `12.` or `12...` possibly glued to the move,
+/// or a SAN move with optional `!`/`?` suffixes.
+fn word_tokens(word: &str) -> Vec<Token> {
+    if let Some(result) = GameResult::parse(word) {
+        return vec![Token::Result(result)];
+    }
+    let san = if word.contains('.') { word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.') } else { word };
+    if san.is_empty() {
+        return Vec::new();
+    }
+    let move_part = san.trim_end_matches(['!', '?']);
+    let suffix_nag = match &san[move_part.len()..] {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    };
+    let mut tokens = vec![Token::San(move_part.to_string())];
+    tokens.extend(suffix_nag.map(Token::Nag));
+    tokens
+}
+
+/// A variation still being read, along with the position after its last move.
+struct OpenVariation {
+    board: Board,
+    last_move: Option<MoveInfo>,
+    variation: Variation,
+}
+
+/// Position before the last move of a line, where a variation of that line starts.
+fn branch_position(board: &Board, last_move: Option<MoveInfo>) -> Result<Board, PgnError> {
+    let last_move = last_move.ok_or(PgnError::VariationWithoutMove)?;
+    let mut board = board.clone();
+    board.undo_move(last_move);
+    Ok(board)
+}
+
+/// Parses every game in `text`. Comments and NAGs are only kept for the main line.
+pub fn parse_pgn(text: &str) -> Result<Vec<Game>, PgnError> {
+    let mut games = Vec::new();
+    let mut game: Option<Game> = None;
+    let mut board = start_pos();
+    let mut last_move = None;
+    // Innermost variation last
+    let mut open_variations: Vec<OpenVariation> = Vec::new();
+    let mut in_movetext = false;
+
+    for token in tokenize(text)? {
+        // A tag after movetext starts the next game, even if the result was missing
+        if in_movetext && matches!(token, Token::Tag(..)) {
+            if !open_variations.is_empty() {
+                return Err(PgnError::UnbalancedVariation);
+            }
+            games.extend(game.take());
+            in_movetext = false;
+        }
+        let current = game.get_or_insert_with(|| Game::new(start_pos()));
+
+        if let Some(open) = open_variations.last_mut() {
+            match token {
+                Token::San(san) => {
+                    let ply = open.variation.ply + open.variation.moves.len();
+                    let mv = open.board.parse_san(&san).map_err(|error| PgnError::IllegalMove { game: games.len() + 1, ply: ply + 1, error })?;
+                    open.last_move = Some(open.board.make_move(mv));
+                    open.variation.moves.push(mv);
+                }
+                Token::VariationStart => {
+                    let branch = OpenVariation {
+                        board: branch_position(&open.board, open.last_move)?,
+                        last_move: None,
+                        variation: Variation { ply: open.variation.ply + open.variation.moves.len() - 1, ..Default::default() },
+                    };
+                    open_variations.push(branch);
+                }
+                Token::VariationEnd => {
+                    let closed = open_variations.pop().unwrap().variation;
+                    if !closed.moves.is_empty() {
+                        match open_variations.last_mut() {
+                            Some(parent) => parent.variation.variations.push(closed),
+                            None => current.variations.push(closed),
+                        }
+                    }
+                }
+                Token::Tag(..) | Token::Result(_) => return Err(PgnError::UnbalancedVariation),
+                Token::Comment(_) | Token::Nag(_) => {}
+            }
+            continue;
+        }
+
+        match token {
+            Token::Tag(name, value) => {
+                if name == "FEN" {
+                    current.start_position = Board::from_fen(&value).map_err(PgnError::InvalidFen)?;
+                }
+                current.tags.push((name, value));
+            }
+            Token::Comment(text) => {
+                if let Some(annotation) = current.annotations.last_mut() {
+                    let (comment, eval) = parse_comment(&text);
+                    annotation.eval = eval.or(annotation.eval);
+                    annotation.comment = match (annotation.comment.take(), comment) {
+                        (Some(first), Some(second)) => Some(format!("{} {}", first, second)),
+                        (first, second) => first.or(second),
+                    };
+                }
+            }
+            Token::Nag(nag) => {
+                if let Some(annotation) = current.annotations.last_mut() {
+                    annotation.nags.push(nag);
+                }
+            }
+            Token::VariationStart => {
+                let branch = OpenVariation {
+                    board: branch_position(&board, if in_movetext { last_move } else { None })?,
+                    last_move: None,
+                    variation: Variation { ply: current.moves.len() - 1, ..Default::default() },
+                };
+                open_variations.push(branch);
+            }
+            Token::VariationEnd => return Err(PgnError::UnbalancedVariation),
+            Token::Result(result) => {
+                current.result = result;
+                games.extend(game.take());
+                in_movetext = false;
+            }
+            Token::San(san) => {
+                if !in_movetext {
+                    board = current.start_position.clone();
+                    in_movetext = true;
+                }
+                let mv = board.parse_san(&san).map_err(|error| PgnError::IllegalMove { game: games.len() + 1, ply: current.moves.len() + 1, error })?;
+                last_move = Some(board.make_move(mv));
+                current.push_move(mv, None);
+            }
+        }
+    }
+    if !open_variations.is_empty() {
+        return Err(PgnError::UnbalancedVariation);
+    }
+    games.extend(game.filter(|game| !game.tags.is_empty() || !game.moves.is_empty()));
+    Ok(games)
+}
+
+pub fn read_pgn_file(path: &str) -> Result<Vec<Game>, PgnError> {
+    let text = fs::read_to_string(path).map_err(|err| PgnError::Io(format!("couldn't read {}: {}", path, err)))?;
+    parse_pgn(&text)
+}
+
+const DEFAULT_ANNOTATION_DEPTH: usize = 10;
+
+/// `annotate <games.pgn> <out.pgn> [--depth N]`, searches the position after every main line move and writes
+/// the games back with the score as `[%eval]` comment. Returns whether all games could be read and written.
+pub fn annotate_cli(args: &[String]) -> bool {
+    let usage = "usage: annotate <games.pgn> <out.pgn> [--depth N]";
+    let mut depth = DEFAULT_ANNOTATION_DEPTH;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--depth" => depth = args.next().and_then(|depth| depth.parse().ok()).unwrap_or(depth),
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        eprintln!("{}", usage);
+        return false;
+    }
+    let mut games = match read_pgn_file(paths[0]) {
+        Ok(games) => games,
+        Err(err) => {
+            eprintln!("{}: {}", paths[0], err);
+            return false;
+        }
+    };
+
+    let mut searcher = Searcher::new();
+    searcher.print_info = false;
+    let limits = SearchLimits { depth: Some(depth), ..Default::default() };
+    for game in games.iter_mut() {
+        let mut board = game.start_position.clone();
+        for (mv, annotation) in game.moves.iter().zip(game.annotations.iter_mut()) {
+            board.make_move(*mv);
+            // Mated or stalemated positions have no line and keep the eval they had
+            if let Some(line) = searcher.search_multi_pv(&mut board, limits, 1).first() {
+                annotation.eval = Some(if board.white_to_move { line.score } else { -line.score });
+            }
+        }
+        game.set_tag("Annotator", "bismuth");
+    }
+
+    let pgn: String = games.iter().map(Game::to_pgn).collect();
+    if let Err(err) = fs::write(paths[1], pgn) {
+        eprintln!("couldn't write {}: {}", paths[1], err);
+        return false;
+    }
+    println!("{} games annotated at depth {}, written to {}", games.len(), depth, paths[1]);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_pgn, GameResult, PgnError};
+    use crate::move_gen_dir::move_gen::Move;
+    use crate::search::search::MATE_VALUE;
+    use crate::uci::convert_mv_to_uci;
+
+    const GAMES: &str = r#"[Event "Casual \"blitz\""]
+[White "A"]
+[Black "B"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 {main line} (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 $1 3. Bb5 a6?! ; Morphy
+4. Ba4 {[%eval 0.31]} Nf6 5. 0-0 1-0
+
+[Event "Second"]
+[SetUp "1"]
+[FEN "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 30"]
+
+30. Ra8# {[%eval #1] mate} *
+"#;
+
+    #[test]
+    fn reads_tags_moves_and_annotations() {
+        let games = parse_pgn(GAMES).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let first = &games[0];
+        assert_eq!(first.tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(first.result, GameResult::WhiteWin);
+        let moves: Vec<String> = first.moves.iter().map(|mv| convert_mv_to_uci(*mv)).collect();
+        assert_eq!(moves, ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5a4", "g8f6", "e1g1"]);
+        assert_eq!(first.annotations[2].comment.as_deref(), Some("main line"));
+        assert_eq!(first.annotations[3].nags, vec![1]);
+        assert_eq!(first.annotations[5].nags, vec![6]);
+        assert_eq!(first.annotations[5].comment.as_deref(), Some("Morphy"));
+        assert_eq!(first.annotations[6].eval, Some(31));
+        assert_eq!(first.variations.len(), 1);
+
+        let second = &games[1];
+        assert_eq!(second.start_position.fullmove_number, 30);
+        assert_eq!(second.annotations[0].eval, Some(MATE_VALUE - 1));
+        assert_eq!(second.annotations[0].comment.as_deref(), Some("mate"));
+        assert_eq!(second.result, GameResult::Unknown);
+    }
+
+    #[test]
+    fn reads_nested_variations_from_their_branch_points() {
+        let games = parse_pgn(GAMES).unwrap();
+        let uci = |moves: &[Move]| moves.iter().map(|mv| convert_mv_to_uci(*mv)).collect::<Vec<_>>();
+
+        // (2. f4 exf4 (2... d5) 3. Nf3) replaces 2. Nf3, the inner variation replaces 2... exf4
+        let variation = &games[0].variations[0];
+        assert_eq!(variation.ply, 2);
+        assert_eq!(uci(&variation.moves), ["f2f4", "e5f4", "g1f3"]);
+        assert_eq!(variation.variations.len(), 1);
+        assert_eq!(variation.variations[0].ply, 3);
+        assert_eq!(uci(&variation.variations[0].moves), ["d7d5"]);
+        assert!(variation.variations[0].variations.is_empty());
+
+        // Variations are read against the position they branch from, not the one the main line reached
+        let games = parse_pgn("1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4) (1. Nf3) e5 (1... c5 2. Nf3 (2. c3)) *").unwrap();
+        let variations = &games[0].variations;
+        assert_eq!(variations.iter().map(|variation| variation.ply).collect::<Vec<_>>(), [0, 0, 1]);
+        assert_eq!(uci(&variations[0].moves), ["d2d4", "d7d5", "c2c4"]);
+        assert_eq!(uci(&variations[1].moves), ["g1f3"]);
+        assert_eq!(uci(&variations[2].moves), ["c7c5", "g1f3"]);
+        assert_eq!(variations[0].variations[0].ply, 1);
+        assert_eq!(uci(&variations[0].variations[0].moves), ["g8f6", "c2c4"]);
+        assert_eq!(variations[2].variations[0].ply, 2);
+        assert_eq!(uci(&variations[2].variations[0].moves), ["c2c3"]);
+    }
+
+    #[test]
+    fn written_games_read_back_the_same() {
+        for game in parse_pgn(GAMES).unwrap() {
+            let pgn = game.to_pgn();
+            let reread = parse_pgn(&pgn).unwrap();
+            assert_eq!(reread.len(), 1, "{}", pgn);
+            assert_eq!(reread[0].moves, game.moves);
+            assert_eq!(reread[0].result, game.result);
+            assert_eq!(reread[0].variations, game.variations);
+            assert_eq!(reread[0].annotations.iter().map(|a| a.eval).collect::<Vec<_>>(), game.annotations.iter().map(|a| a.eval).collect::<Vec<_>>());
+        }
+        let pgn = parse_pgn(GAMES).unwrap()[1].to_pgn();
+        assert!(pgn.contains("30. Ra8# {[%eval #1] mate} *"), "{}", pgn);
+        let pgn = parse_pgn(GAMES).unwrap()[0].to_pgn();
+        assert!(pgn.contains("2. Nf3 {main line} (2. f4 exf4 (2... d5) 3. Nf3) 2... Nc6 $1"), "{}", pgn);
+    }
+
+    #[test]
+    fn rejects_broken_games() {
+        assert!(matches!(parse_pgn("1. e4 e4"), Err(PgnError::IllegalMove { game: 1, ply: 2, .. })));
+        assert_eq!(parse_pgn("1. e4 {unterminated"), Err(PgnError::UnterminatedComment));
+        assert_eq!(parse_pgn("1. e4 (1. d4 *"), Err(PgnError::UnbalancedVariation));
+        assert_eq!(parse_pgn("(1. d4) 1. e4 *"), Err(PgnError::VariationWithoutMove));
+        assert!(matches!(parse_pgn("1. e4 e5 (1... e4) *"), Err(PgnError::IllegalMove { game: 1, ply: 2, .. })));
+        assert!(matches!(parse_pgn("[Event]"), Err(PgnError::InvalidTag(_))));
+    }
+}
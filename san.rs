@@ -0,0 +1,242 @@
+use crate::helpers::{index_to_sq, sq_to_index};
+use crate::move_gen_dir::move_gen::{gives_check, Castling, GenerationMode, Move, PieceType, Square};
+use crate::{generate_all_moves, Board};
+use std::fmt;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SanError {
+    InvalidSyntax(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::InvalidSyntax(san) => write!(f, "{} is not a SAN move", san),
+            SanError::IllegalMove(san) => write!(f, "{} is not legal in this position", san),
+            SanError::AmbiguousMove(san) => write!(f, "{} matches more than one legal move", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+const fn piece_letter(piece_type: PieceType) -> Option<char> {
+    match piece_type {
+        PieceType::Knight => Some('N'),
+        PieceType::Bishop => Some('B'),
+        PieceType::Rook => Some('R'),
+        PieceType::Queen => Some('Q'),
+        PieceType::King => Some('K'),
+        _ => None,
+    }
+}
+
+const fn piece_from_letter(letter: char) -> Option<PieceType> {
+    match letter {
+        'N' => Some(PieceType::Knight),
+        'B' => Some(PieceType::Bishop),
+        'R' => Some(PieceType::Rook),
+        'Q' => Some(PieceType::Queen),
+        'K' => Some(PieceType::King),
+        _ => None,
+    }
+}
+
+fn square_name(square: u64) -> String {
+    index_to_sq(square.trailing_zeros() as usize).to_string().to_lowercase()
+}
+
+impl Board {
+    /// Standard algebraic notation of the legal move `mv`, e.g. `Nbd7`, `exd6`, `e8=Q+` or `O-O-O#`.
+    pub fn move_to_san(&self, mv: Move) -> String {
+        let legal_moves = generate_all_moves(self, &GenerationMode::All);
+
+        let mut san = match mv.castle {
+            Castling::KingSide => "O-O".to_string(),
+            Castling::QueenSide => "O-O-O".to_string(),
+            Castling::NoCastle => {
+                let mut san = String::new();
+                let start = mv.start_square.trailing_zeros() as usize;
+
+                match piece_letter(mv.piece_type) {
+                    Some(letter) => {
+                        san.push(letter);
+
+                        // Other pieces of the same kind which can reach the same square
+                        let rivals: Vec<usize> = (0..legal_moves.moves_added)
+                            .map(|i| legal_moves.moves[i])
+                            .filter(|other| other.piece_type == mv.piece_type && other.end_square == mv.end_square && other.start_square != mv.start_square)
+                            .map(|other| other.start_square.trailing_zeros() as usize)
+                            .collect();
+                        if !rivals.is_empty() {
+                            let start_name = square_name(mv.start_square);
+                            if rivals.iter().all(|&rival| rival % 8 != start % 8) {
+                                san.push_str(&start_name[..1]);
+                            } else if rivals.iter().all(|&rival| rival / 8 != start / 8) {
+                                san.push_str(&start_name[1..]);
+                            } else {
+                                san.push_str(&start_name);
+                            }
+                        }
+                    }
+                    None if mv.capture != PieceType::NoPiece => san.push_str(&square_name(mv.start_square)[..1]),
+                    None => {}
+                }
+
+                if mv.capture != PieceType::NoPiece {
+                    san.push('x');
+                }
+                san.push_str(&square_name(mv.end_square));
+
+                if let Some(letter) = piece_letter(mv.promotion) {
+                    san.push('=');
+                    san.push(letter);
+                }
+                san
+            }
+        };
+
+        if gives_check(self, &mv) {
+            let mut board = self.clone();
+            board.make_move(mv);
+            let replies = generate_all_moves(&board, &GenerationMode::All);
+            san.push(if replies.moves_added == 0 { '#' } else { '+' });
+        }
+        san
+    }
+
+    /// Finds the legal move written as `san`. Check marks and annotations are ignored, and so are
+    /// redundant disambiguation, a missing `x` or a promotion without `=`.
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let legal_moves = generate_all_moves(self, &GenerationMode::All);
+        let mut legal_moves = (0..legal_moves.moves_added).map(|i| legal_moves.moves[i]);
+
+        let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        let castle = match trimmed {
+            "O-O" | "0-0" => Some(Castling::KingSide),
+            "O-O-O" | "0-0-0" => Some(Castling::QueenSide),
+            _ => None,
+        };
+        if let Some(castle) = castle {
+            return legal_moves.find(|mv| mv.castle == castle).ok_or(SanError::IllegalMove(san.to_string()));
+        }
+
+        let invalid = || SanError::InvalidSyntax(san.to_string());
+        let mut chars: Vec<char> = trimmed.chars().collect();
+
+        let piece_type = match chars.first().and_then(|&letter| piece_from_letter(letter)) {
+            Some(piece_type) => {
+                chars.remove(0);
+                piece_type
+            }
+            None => PieceType::Pawn,
+        };
+
+        let mut promotion = PieceType::NoPiece;
+        if let Some(letter) = chars.last().and_then(|&letter| piece_from_letter(letter)) {
+            promotion = letter;
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        if chars.len() < 2 {
+            return Err(invalid());
+        }
+        let destination: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let end_square = destination.to_uppercase().parse::<Square>().map_err(|_| invalid())?;
+        let end_square = 1u64 << sq_to_index(end_square);
+
+        if chars.last() == Some(&'x') {
+            chars.pop();
+        }
+        let mut from_file = None;
+        let mut from_rank = None;
+        for character in chars {
+            match character {
+                'a'..='h' if from_file.is_none() => from_file = Some(character as usize - 'a' as usize),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(character as usize - '1' as usize),
+                _ => return Err(invalid()),
+            }
+        }
+
+        let mut candidates = legal_moves.filter(|mv| {
+            let start = mv.start_square.trailing_zeros() as usize;
+            mv.castle == Castling::NoCastle
+                && mv.piece_type == piece_type
+                && mv.end_square == end_square
+                && mv.promotion == promotion
+                && from_file.is_none_or(|file| start % 8 == file)
+                && from_rank.is_none_or(|rank| start / 8 == rank)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (None, _) => Err(SanError::IllegalMove(san.to_string())),
+            (Some(_), Some(_)) => Err(SanError::AmbiguousMove(san.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SanError;
+    use crate::fen_import::make_board;
+    use crate::move_gen_dir::move_gen::GenerationMode;
+    use crate::move_gen_dir::move_gen_tests::TEST_POSITIONS;
+    use crate::generate_all_moves;
+
+    fn san_of(fen: &str, uci: &str) -> String {
+        let board = make_board(fen);
+        let moves = generate_all_moves(&board, &GenerationMode::All);
+        let mv = (0..moves.moves_added).map(|i| moves.moves[i]).find(|mv| crate::uci::convert_mv_to_uci(*mv) == uci).unwrap();
+        board.move_to_san(mv)
+    }
+
+    #[test]
+    fn formats_san() {
+        let kiwipete = TEST_POSITIONS[1];
+        assert_eq!(san_of(kiwipete, "e1g1"), "O-O");
+        assert_eq!(san_of(kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san_of(kiwipete, "e5f7"), "Nxf7");
+        assert_eq!(san_of(kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san_of(kiwipete, "c3b1"), "Nb1");
+        assert_eq!(san_of(kiwipete, "e2a6"), "Bxa6");
+        assert_eq!(san_of("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "a1d1"), "Rad1");
+        assert_eq!(san_of("4k3/8/8/8/R7/8/8/R3K3 w - - 0 1", "a1a2"), "R1a2");
+        assert_eq!(san_of("7k/8/8/8/Q1Q5/8/Q7/4K3 w - - 0 1", "a4b3"), "Qa4b3");
+        assert_eq!(san_of("3k4/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), "b8=Q+");
+        assert_eq!(san_of("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8#");
+        assert_eq!(san_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), "exd6");
+    }
+
+    #[test]
+    fn parse_san_round_trips_every_legal_move() {
+        for fen in TEST_POSITIONS {
+            let board = make_board(fen);
+            let moves = generate_all_moves(&board, &GenerationMode::All);
+            for i in 0..moves.moves_added {
+                let san = board.move_to_san(moves.moves[i]);
+                assert_eq!(board.parse_san(&san), Ok(moves.moves[i]), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_san_is_lenient_but_rejects_bad_moves() {
+        let board = make_board(TEST_POSITIONS[0]);
+        assert_eq!(crate::uci::convert_mv_to_uci(board.parse_san("Ngf3").unwrap()), "g1f3");
+        assert_eq!(crate::uci::convert_mv_to_uci(board.parse_san("e4!?").unwrap()), "e2e4");
+        assert_eq!(board.parse_san("e5"), Err(SanError::IllegalMove("e5".to_string())));
+        assert_eq!(board.parse_san("Zz9"), Err(SanError::InvalidSyntax("Zz9".to_string())));
+
+        let board = make_board("4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(board.parse_san("Rd1"), Err(SanError::AmbiguousMove("Rd1".to_string())));
+
+        let board = make_board("3k4/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(crate::uci::convert_mv_to_uci(board.parse_san("b8N").unwrap()), "b7b8n");
+    }
+}
@@ -0,0 +1,119 @@
+use crate::move_gen_dir::move_gen::Move;
+use crate::search::pv_table::MAX_PLY;
+
+/// History scores stay within +-HISTORY_MAX, below the bonus of killers and non-capturing promotions
+const HISTORY_MAX: i32 = 4_000;
+const FIRST_KILLER_SCORE: i32 = 9_000;
+const SECOND_KILLER_SCORE: i32 = 8_900;
+const COUNTERMOVE_SCORE: i32 = 8_800;
+
+/// What the search learned about quiet moves, used to order them. Every search thread keeps its own.
+pub struct MoveHistory {
+    /// Two quiet moves per ply which recently caused a beta cutoff
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    /// Butterfly table by side to move, from square and to square
+    history: [[[i32; 64]; 64]; 2],
+    /// The quiet move which refuted the previous move, by its from and to square
+    countermoves: [[Option<Move>; 64]; 64],
+}
+
+fn squares(mv: Move) -> (usize, usize) {
+    (mv.start_square.trailing_zeros() as usize, mv.end_square.trailing_zeros() as usize)
+}
+
+impl MoveHistory {
+    pub fn new() -> Self {
+        MoveHistory { killers: [[None; 2]; MAX_PLY], history: [[[0; 64]; 64]; 2], countermoves: [[None; 64]; 64] }
+    }
+
+    /// Killers belong to the old root, the history is kept but counts half as much.
+    pub fn new_search(&mut self) {
+        self.killers = [[None; 2]; MAX_PLY];
+        for score in self.history.iter_mut().flatten().flatten() {
+            *score /= 2;
+        }
+    }
+
+    pub const fn killers(&self, ply: usize) -> [Option<Move>; 2] {
+        self.killers[ply]
+    }
+
+    pub fn is_killer(&self, ply: usize, mv: Move) -> bool {
+        self.killers[ply].contains(&Some(mv))
+    }
+
+    /// Ordering score of the quiet move `mv`, `previous_move` is the opponent's move that led here.
+    pub fn quiet_score(&self, mv: Move, ply: usize, white_to_move: bool, previous_move: Option<Move>) -> i32 {
+        if self.killers[ply][0] == Some(mv) {
+            return FIRST_KILLER_SCORE;
+        }
+        if self.killers[ply][1] == Some(mv) {
+            return SECOND_KILLER_SCORE;
+        }
+        if let Some(previous_move) = previous_move {
+            let (from, to) = squares(previous_move);
+            if self.countermoves[from][to] == Some(mv) {
+                return COUNTERMOVE_SCORE;
+            }
+        }
+        let (from, to) = squares(mv);
+        self.history[white_to_move as usize][from][to]
+    }
+
+    /// The quiet move `mv` caused a beta cutoff after `tried_quiets` failed to, reward it and punish those.
+    pub fn update(&mut self, mv: Move, ply: usize, white_to_move: bool, previous_move: Option<Move>, depth_left: i32, tried_quiets: &[Move]) {
+        if self.killers[ply][0] != Some(mv) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(mv);
+        }
+        if let Some(previous_move) = previous_move {
+            let (from, to) = squares(previous_move);
+            self.countermoves[from][to] = Some(mv);
+        }
+
+        let bonus = (depth_left * depth_left).min(HISTORY_MAX / 2);
+        self.add_history(mv, white_to_move, bonus);
+        for &tried in tried_quiets {
+            self.add_history(tried, white_to_move, -bonus);
+        }
+    }
+
+    // Gravity: the closer a score already is to the limit, the less it moves further towards it
+    fn add_history(&mut self, mv: Move, white_to_move: bool, bonus: i32) {
+        let (from, to) = squares(mv);
+        let score = &mut self.history[white_to_move as usize][from][to];
+        *score += bonus - *score * bonus.abs() / HISTORY_MAX;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MoveHistory, COUNTERMOVE_SCORE, FIRST_KILLER_SCORE, HISTORY_MAX, SECOND_KILLER_SCORE};
+    use crate::fen_import::start_pos;
+
+    #[test]
+    fn cutoffs_feed_killers_countermoves_and_history() {
+        let board = start_pos();
+        let [e4, d4, nf3, c4] = ["e4", "d4", "Nf3", "c4"].map(|san| board.parse_san(san).unwrap());
+        let mut history = MoveHistory::new();
+
+        history.update(e4, 3, true, Some(c4), 4, &[nf3]);
+        history.update(d4, 3, true, None, 4, &[]);
+        assert_eq!(history.quiet_score(d4, 3, true, None), FIRST_KILLER_SCORE);
+        assert_eq!(history.quiet_score(e4, 3, true, None), SECOND_KILLER_SCORE);
+        assert_eq!(history.quiet_score(e4, 2, true, Some(c4)), COUNTERMOVE_SCORE);
+        assert!(history.quiet_score(e4, 2, true, None) > 0);
+        assert!(history.quiet_score(nf3, 2, true, None) < 0);
+        assert_eq!(history.quiet_score(e4, 2, false, None), 0);
+
+        for _ in 0..1000 {
+            history.update(e4, 5, true, None, 40, &[nf3]);
+        }
+        assert!(history.quiet_score(e4, 2, true, None) <= HISTORY_MAX);
+        assert!(history.quiet_score(nf3, 2, true, None) >= -HISTORY_MAX);
+
+        history.new_search();
+        assert!(!history.is_killer(5, e4));
+        assert!(history.quiet_score(e4, 2, true, None) <= HISTORY_MAX / 2);
+    }
+}
@@ -1,3 +1,7 @@
 pub mod search;
 mod transposition_table;
-pub mod repition_table;
\ No newline at end of file
+pub mod repition_table;
+pub mod time_manager;
+pub mod pv_table;
+pub mod history;
+pub mod move_picker;
\ No newline at end of file
@@ -0,0 +1,278 @@
+use crate::move_gen_dir::move_gen::{Castling, GenerationMode, Move, PieceType};
+use crate::move_gen_dir::move_gen::PieceType::NoPiece;
+use crate::move_list::{MoveList, MAX_LEGAL_MOVE_COUNT};
+use crate::search::history::MoveHistory;
+use crate::{append_moves, Board};
+
+/// Captures which don't lose material score above this, bad captures below it
+const GOOD_CAPTURE: i32 = 1_000_000;
+/// Quiet promotions go before the quiet moves ordered by history
+const PROMOTION: i32 = 5_000;
+const CASTLING: i32 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    TtMove,
+    GenerateCaptures,
+    GoodCaptures,
+    GenerateChecks,
+    Checks,
+    Killers,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Hands out the moves of a node best first. Moves are only generated once the stage before them
+/// is used up, so a TT move or a good capture that cuts off never pays for generating the quiet moves.
+pub struct MovePicker {
+    stage: Stage,
+    tt_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    killer_index: usize,
+    ply: usize,
+    previous_move: Option<Move>,
+    /// Quiescence only searches the good captures
+    captures_only: bool,
+    /// Followed by the quiet checks, for the first ply of quiescence
+    quiet_checks: bool,
+    /// Quiescence in check, which may be deeper than the killers and history are kept for
+    evasions: bool,
+    /// Captures first, the quiet moves are appended behind them
+    moves: MoveList,
+    scores: [i32; MAX_LEGAL_MOVE_COUNT],
+    /// Index of the next move to hand out
+    current: usize,
+    captures_end: usize,
+    /// The bad captures are the ones left in `moves[bad_captures_start..captures_end]`
+    bad_captures_start: usize,
+}
+
+const fn piece_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn => 208,
+        PieceType::Knight => 781,
+        PieceType::Bishop => 825,
+        PieceType::Rook => 1276,
+        PieceType::Queen => 2538,
+        PieceType::King => 10_000,
+        PieceType::NoPiece => 0,
+    }
+}
+
+impl MovePicker {
+    /// `previous_move` is the opponent's move that led here, it decides which countermove gets a bonus.
+    pub fn new(tt_move: Option<Move>, killers: [Option<Move>; 2], ply: usize, previous_move: Option<Move>) -> Self {
+        MovePicker {
+            stage: Stage::TtMove,
+            tt_move,
+            killers,
+            killer_index: 0,
+            ply,
+            previous_move,
+            captures_only: false,
+            quiet_checks: false,
+            evasions: false,
+            moves: MoveList::new(),
+            scores: [0; MAX_LEGAL_MOVE_COUNT],
+            current: 0,
+            captures_end: 0,
+            bad_captures_start: 0,
+        }
+    }
+
+    /// Only the captures which don't lose material, for quiescence.
+    pub fn captures() -> Self {
+        MovePicker { stage: Stage::GenerateCaptures, captures_only: true, ..Self::new(None, [None; 2], 0, None) }
+    }
+
+    /// Every move of a side in check, for quiescence. Quiet moves are not ordered by killers or history.
+    pub fn evasions() -> Self {
+        MovePicker { stage: Stage::GenerateCaptures, evasions: true, ..Self::new(None, [None; 2], 0, None) }
+    }
+
+    /// The good captures, then the quiet checks which don't give away the moved piece.
+    pub fn captures_and_checks() -> Self {
+        MovePicker { quiet_checks: true, ..Self::captures() }
+    }
+
+    pub fn next(&mut self, board: &mut Board, history: &MoveHistory) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::TtMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if self.tt_move.is_some() {
+                        return self.tt_move;
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    append_moves(board, &mut self.moves, &GenerationMode::Capture);
+                    self.captures_end = self.moves.moves_added;
+                    for i in 0..self.captures_end {
+                        self.scores[i] = capture_score(board, &self.moves.moves[i]);
+                    }
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => match self.pick_best(self.captures_end) {
+                    Some((mv, score)) if score >= GOOD_CAPTURE => {
+                        if Some(mv) != self.tt_move {
+                            return Some(mv);
+                        }
+                    }
+                    picked => {
+                        // The best capture left is a bad one, so all of them are. They wait until the quiet moves are done
+                        if picked.is_some() {
+                            self.current -= 1;
+                        }
+                        self.bad_captures_start = self.current;
+                        self.stage = match (self.captures_only, self.quiet_checks) {
+                            (false, _) => Stage::Killers,
+                            (true, true) => Stage::GenerateChecks,
+                            (true, false) => Stage::Done,
+                        };
+                    }
+                },
+                Stage::GenerateChecks => {
+                    append_moves(board, &mut self.moves, &GenerationMode::Check);
+                    self.current = self.captures_end;
+                    self.stage = Stage::Checks;
+                }
+                Stage::Checks => {
+                    if self.current == self.moves.moves_added {
+                        self.stage = Stage::Done;
+                        continue;
+                    }
+                    self.current += 1;
+                    let mv = self.moves.moves[self.current - 1];
+                    if board.see_ge(mv, 0) {
+                        return Some(mv);
+                    }
+                }
+                Stage::Killers => {
+                    if self.killer_index == self.killers.len() {
+                        self.stage = Stage::GenerateQuiets;
+                        continue;
+                    }
+                    let killer = self.killers[self.killer_index];
+                    self.killer_index += 1;
+                    // Killers come from other positions, so they have to be checked before they are played here
+                    if let Some(killer) = killer {
+                        if Some(killer) != self.tt_move && board.decode_move(killer.to_u16()) == Some(killer) {
+                            return Some(killer);
+                        }
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    append_moves(board, &mut self.moves, &GenerationMode::Quiet);
+                    for i in self.captures_end..self.moves.moves_added {
+                        self.scores[i] = self.quiet_score(board, history, &self.moves.moves[i]);
+                    }
+                    self.current = self.captures_end;
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match self.pick_best(self.moves.moves_added) {
+                    Some((mv, _)) => {
+                        if Some(mv) != self.tt_move && !self.killers.contains(&Some(mv)) {
+                            return Some(mv);
+                        }
+                    }
+                    None => {
+                        self.current = self.bad_captures_start;
+                        self.stage = Stage::BadCaptures;
+                    }
+                },
+                Stage::BadCaptures => match self.pick_best(self.captures_end) {
+                    Some((mv, _)) => {
+                        if Some(mv) != self.tt_move {
+                            return Some(mv);
+                        }
+                    }
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    // One step of a selection sort: swaps the best move up to `end` to the front and hands it out.
+    // Nodes usually cut off after a few moves, so sorting the whole list would mostly be wasted.
+    fn pick_best(&mut self, end: usize) -> Option<(Move, i32)> {
+        if self.current >= end {
+            return None;
+        }
+        let mut best = self.current;
+        for i in self.current + 1..end {
+            if self.scores[i] > self.scores[best] {
+                best = i;
+            }
+        }
+        self.moves.moves.swap(self.current, best);
+        self.scores.swap(self.current, best);
+        self.current += 1;
+        Some((self.moves.moves[self.current - 1], self.scores[self.current - 1]))
+    }
+
+    fn quiet_score(&self, board: &Board, history: &MoveHistory, mv: &Move) -> i32 {
+        if mv.promotion != NoPiece {
+            return PROMOTION + piece_value(mv.promotion);
+        }
+        if self.evasions {
+            return 0;
+        }
+        let castling = if mv.castle != Castling::NoCastle { CASTLING } else { 0 };
+        castling + history.quiet_score(*mv, self.ply, board.white_to_move, self.previous_move)
+    }
+}
+
+/// MVV-LVA, captures that lose material in the exchange count as bad captures.
+fn capture_score(board: &Board, mv: &Move) -> i32 {
+    let score = 8 * (piece_value(mv.capture) + piece_value(mv.promotion)) - piece_value(mv.piece_type);
+    if board.see_ge(*mv, 0) {
+        GOOD_CAPTURE + score
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fen_import::make_board;
+    use crate::move_gen_dir::check_mask::get_checkmask;
+    use crate::move_gen_dir::move_gen::{GenerationMode, PieceType};
+    use crate::move_gen_dir::move_gen_tests::{for_each_position, CHECK_POSITIONS, TEST_POSITIONS};
+    use crate::move_gen_dir::precomputed_magics::{SQUARES_BETWEEN_DIAGONAL, SQUARES_BETWEEN_STRAIGHT};
+    use crate::{generate_all_moves, Board};
+
+    // Quiet moves of GenerationMode::All which leave the opponent in check, sorted
+    fn quiet_checks_by_make_move(board: &mut Board) -> Vec<u16> {
+        let move_list = generate_all_moves(board, &GenerationMode::All);
+        let mut checks = Vec::new();
+        for i in 0..move_list.moves_added {
+            let mv = move_list.moves[i];
+            if mv.capture != PieceType::NoPiece {
+                continue;
+            }
+            let last_mv_info = board.make_move(mv);
+            let (checkmask, _pinmask) = get_checkmask(board, &SQUARES_BETWEEN_STRAIGHT, &SQUARES_BETWEEN_DIAGONAL);
+            board.undo_move(last_mv_info);
+            if checkmask != 0xffffffffffffffff {
+                checks.push(mv.to_u16());
+            }
+        }
+        checks.sort();
+        checks
+    }
+
+    #[test]
+    fn check_mode_generates_exactly_the_quiet_checks() {
+        for fen in TEST_POSITIONS.into_iter().chain(CHECK_POSITIONS) {
+            for_each_position(&mut make_board(fen), 2, &mut |board| {
+                let generated = generate_all_moves(board, &GenerationMode::Check);
+                let mut checks: Vec<u16> = (0..generated.moves_added).map(|i| generated.moves[i].to_u16()).collect();
+                checks.sort();
+                assert_eq!(checks, quiet_checks_by_make_move(board), "{}", board.to_fen());
+            });
+        }
+    }
+}
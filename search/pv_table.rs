@@ -0,0 +1,36 @@
+use crate::move_gen_dir::move_gen::Move;
+use crate::search::search::NULL_MOVE;
+
+pub const MAX_PLY: usize = 128;
+
+/// Triangular PV table: row `ply` holds the best line found from that ply onwards.
+pub struct PvTable {
+    moves: Vec<[Move; MAX_PLY]>,
+    length: [usize; MAX_PLY],
+}
+
+impl PvTable {
+    pub fn new() -> Self {
+        PvTable { moves: vec![[NULL_MOVE.choosen_move; MAX_PLY]; MAX_PLY], length: [0; MAX_PLY] }
+    }
+
+    pub fn reset(&mut self, ply: usize) {
+        self.length[ply] = 0;
+    }
+
+    /// Sets `mv` as the best move at `ply` followed by the line of the child node.
+    pub fn update(&mut self, ply: usize, mv: Move) {
+        let child_length = if ply + 1 < MAX_PLY { self.length[ply + 1] } else { 0 };
+        let (parent, child) = self.moves.split_at_mut(ply + 1);
+
+        parent[ply][0] = mv;
+        if child_length != 0 {
+            parent[ply][1..=child_length].copy_from_slice(&child[0][..child_length]);
+        }
+        self.length[ply] = child_length + 1;
+    }
+
+    pub fn line(&self, ply: usize) -> &[Move] {
+        &self.moves[ply][..self.length[ply]]
+    }
+}
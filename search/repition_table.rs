@@ -1,33 +1,43 @@
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+/// Hashes of all positions played before the current one, in game order.
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct RepetitionTable {
-    hashes: [u64; 256],
-    count: usize,
+    hashes: Vec<u64>,
 }
 impl RepetitionTable {
     pub const fn new() -> Self {
-        RepetitionTable { hashes: [0; 256], count: 0 }
+        RepetitionTable { hashes: Vec::new() }
     }
     pub fn add(&mut self, hash: u64) {
-        if self.count < self.hashes.len() {
-            self.hashes[self.count] = hash;
-            self.count += 1;
-        }
+        self.hashes.push(hash);
     }
     pub fn pop_last(&mut self) {
-        self.count -= 1;
-        self.hashes[self.count] = 0;
+        self.hashes.pop();
     }
-    pub fn contains(&self, hash: u64) -> bool {
-        let mut rep_count = 0;
-        for i in 0..self.count {
-            if self.hashes[i] == hash {
-                rep_count += 1;
-            }
-            if rep_count >= 3 {
+
+    /// Earlier positions with the same side to move, nearest first, that can still repeat `hash`.
+    /// Nothing before the last irreversible move (`halfmove_clock` plies back) can repeat.
+    fn earlier_occurrences(&self, hash: u64, halfmove_clock: u32) -> impl Iterator<Item = usize> + '_ {
+        let reach = (halfmove_clock as usize).min(self.hashes.len());
+        // A position needs at least four plies to come back
+        (4..=reach).step_by(2).filter(move |&distance| self.hashes[self.hashes.len() - distance] == hash)
+    }
+
+    /// Threefold repetition, the draw a player can claim in the game.
+    pub fn is_threefold(&self, hash: u64, halfmove_clock: u32) -> bool {
+        self.earlier_occurrences(hash, halfmove_clock).nth(1).is_some()
+    }
+
+    /// In search a single repetition of a position from the current search path is already a draw,
+    /// repeating a position from before the root still needs it to occur three times.
+    pub fn is_draw_in_search(&self, hash: u64, halfmove_clock: u32, ply_from_root: usize) -> bool {
+        let mut occurrences = 0;
+        for distance in self.earlier_occurrences(hash, halfmove_clock) {
+            occurrences += 1;
+            if distance <= ply_from_root || occurrences >= 2 {
                 return true;
             }
         }
-        return false;
+        false
     }
-}
\ No newline at end of file
+}
@@ -1,15 +1,23 @@
 use crate::evaluation::evaluation::evaluate_board;
 use crate::move_gen_dir::move_gen::Castling::NoCastle;
 use crate::move_gen_dir::move_gen::PieceType::NoPiece;
-use crate::move_gen_dir::move_gen::{GenerationMode, Move};
-use crate::OpeningBook::work_with_opening_book::{find_opening_move, get_book_moves, load_opening_book, unpack_move};
-use crate::{generate_all_moves, Board, GameState};
+use crate::move_gen_dir::move_gen::{gives_check, GenerationMode, Move};
+use crate::{generate_all_moves, Board};
 use rand::Rng;
 use std::collections::HashMap;
 use std::process::exit;
-use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use std::sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc};
+use std::thread;
+use std::time::Duration;
 use crate::search::transposition_table::{TranspositionTable, LOOKUP_FAILED};
 use crate::search::transposition_table::NodeType::{Exact, LowerBound, UpperBound};
+use crate::search::time_manager::{SearchLimits, TimeManager};
+use crate::uci_options::DEFAULT_HASH_MB;
+use crate::search::pv_table::{PvTable, MAX_PLY};
+use crate::search::history::MoveHistory;
+use crate::search::move_picker::MovePicker;
+use crate::uci::convert_mv_to_uci;
+use lazy_static::lazy_static;
 
 pub struct  Searcher {
     pub current_iteration_depth: usize,
@@ -18,8 +26,29 @@ pub struct  Searcher {
     pub best_move: EngineMove,
     pub has_searched_one_move: bool,
     pub depth : usize,
-    pub transposition_table: TranspositionTable,
-    pub stop: Arc<AtomicBool>
+    pub transposition_table: Arc<TranspositionTable>,
+    pub stop: Arc<AtomicBool>,
+    pub pondering: Arc<AtomicBool>,
+    pub limits: SearchLimits,
+    pub time_manager: TimeManager,
+    pub pv_table: PvTable,
+    pub principal_variation: Vec<Move>,
+    pub print_info: bool,
+    pub multi_pv: usize,
+    pub multi_pv_lines: Vec<ScoredLine>,
+    pv_index: usize,
+    excluded_root_moves: Vec<Move>,
+    helpers: Vec<Searcher>,
+    helper_id: usize,
+    helper_nodes: Arc<AtomicU64>,
+    published_nodes: u64,
+    /// Set right before searching a null move, so the reply doesn't pass again
+    after_null_move: bool,
+    /// Turned off while a null-move cutoff is verified
+    null_move_allowed: bool,
+    history: Box<MoveHistory>,
+    /// The move made at each ply of the current line, None for a null move
+    current_line: [Option<Move>; MAX_PLY],
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -27,11 +56,48 @@ pub struct EngineMove {
     pub choosen_move: Move,
     pub eval: i32,
 }
-const MATE_VALUE: i32 = 10_000_000;
+
+/// One MultiPV line: the exact score of the root move and the line that follows it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ScoredLine {
+    pub score: i32,
+    pub moves: Vec<Move>,
+}
+pub const MATE_VALUE: i32 = 10_000_000;
+const TIME_CHECK_INTERVAL: u64 = 2048;
+const NULL_MOVE_MIN_DEPTH: i32 = 3;
+/// From here on a null-move cutoff is only trusted after a reduced search without null moves agrees
+const NULL_MOVE_VERIFICATION_DEPTH: i32 = 10;
+const LMR_MIN_DEPTH: i32 = 3;
+/// The first moves of the ordered list are always searched at full depth
+const LMR_FULL_DEPTH_MOVES: usize = 3;
+/// Close to the leaves, moves losing more than the margin per remaining ply in the exchange on their target square are skipped
+const SEE_PRUNING_DEPTH: i32 = 6;
+const SEE_QUIET_MARGIN: i32 = 60;
+const SEE_CAPTURE_MARGIN: i32 = 100;
+/// Quiet moves searched before a cutoff lose history, beyond this many the rest are left alone
+const MAX_TRIED_QUIETS: usize = 64;
+
+lazy_static! {
+    /// Late move reduction by remaining depth and number of moves searched before
+    static ref LMR_REDUCTIONS: [[i32; 64]; 64] = {
+        let mut reductions = [[0; 64]; 64];
+        for (depth, by_move_number) in reductions.iter_mut().enumerate().skip(1) {
+            for (move_number, reduction) in by_move_number.iter_mut().enumerate().skip(1) {
+                *reduction = (0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25) as i32;
+            }
+        }
+        reductions
+    };
+}
 
 pub const NULL_MOVE: EngineMove = EngineMove{ choosen_move: Move{start_square:0,end_square:0,capture:NoPiece,piece_type:NoPiece,promotion:NoPiece,castle:NoCastle,en_passant:false}, eval: 0 };
 impl Searcher {
     pub fn new() -> Self {
+        Self::with_transposition_table(Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)))
+    }
+
+    pub fn with_transposition_table(transposition_table: Arc<TranspositionTable>) -> Self {
         Self{
             current_iteration_depth: 0,
             nodes: 0,
@@ -39,140 +105,460 @@ impl Searcher {
             best_move: NULL_MOVE,
             has_searched_one_move: false,
             depth: 0,
-            transposition_table: TranspositionTable::new(128),
-            stop: Arc::new(AtomicBool::new(false))
+            transposition_table,
+            stop: Arc::new(AtomicBool::new(false)),
+            pondering: Arc::new(AtomicBool::new(false)),
+            limits: SearchLimits::infinite(),
+            time_manager: TimeManager::new(&SearchLimits::infinite(), true),
+            pv_table: PvTable::new(),
+            principal_variation: Vec::new(),
+            print_info: true,
+            multi_pv: 1,
+            multi_pv_lines: Vec::new(),
+            pv_index: 0,
+            excluded_root_moves: Vec::new(),
+            helpers: Vec::new(),
+            helper_id: 0,
+            helper_nodes: Arc::new(AtomicU64::new(0)),
+            published_nodes: 0,
+            after_null_move: false,
+            null_move_allowed: true,
+            history: Box::new(MoveHistory::new()),
+            current_line: [None; MAX_PLY],
         }
     }
 
-    pub fn iterative_deepening(&mut self, board: &mut Board) {
+    pub fn resize_hash(&mut self, size_mb: usize) {
+        // Helpers pick up the new table when the next search starts
+        self.transposition_table = Arc::new(TranspositionTable::new(size_mb));
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.helpers = (1..threads.max(1))
+            .map(|helper_id| {
+                let mut helper = Searcher::with_transposition_table(self.transposition_table.clone());
+                helper.helper_id = helper_id;
+                helper.print_info = false;
+                helper
+            })
+            .collect();
+    }
+
+    /// Lazy SMP: the helpers search the same position on their own threads and only share the
+    /// transposition table with us. The result of this searcher is the one that gets reported.
+    pub fn search(&mut self, board: &mut Board, limits: SearchLimits) {
+        let mut helpers = std::mem::take(&mut self.helpers);
+        self.helper_nodes.store(0, Ordering::Relaxed);
+
+        // Helpers don't manage time, they run until we set the stop flag
+        let helper_limits = SearchLimits { depth: Some(limits.max_depth()), ..SearchLimits::infinite() };
+        let has_helpers = !helpers.is_empty();
+
+        thread::scope(|scope| {
+            for helper in helpers.iter_mut() {
+                helper.transposition_table = self.transposition_table.clone();
+                helper.stop = self.stop.clone();
+                helper.helper_nodes = self.helper_nodes.clone();
+
+                let mut helper_board = board.clone();
+                scope.spawn(move || helper.iterative_deepening(&mut helper_board, helper_limits));
+            }
+
+            self.iterative_deepening(board, limits);
+            if has_helpers {
+                self.stop.store(true, Ordering::SeqCst);
+            }
+        });
+
+        self.helpers = helpers;
+    }
+
+    pub fn total_nodes(&self) -> u64 {
+        self.nodes + self.helper_nodes.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_hash(&mut self) {
+        self.transposition_table.clear();
+    }
+
+    pub fn iterative_deepening(&mut self, board: &mut Board, limits: SearchLimits) {
         self.best_move = NULL_MOVE;
         self.best_move_this_iteration = NULL_MOVE;
+        self.nodes = 0;
+        self.published_nodes = 0;
+        self.depth = 0;
+        self.current_iteration_depth = 0;
+        self.principal_variation.clear();
+        self.multi_pv_lines.clear();
+        self.limits = limits;
+        self.time_manager = TimeManager::new(&limits, board.white_to_move);
+        self.history.new_search();
+
+        // Odd helpers skip the first depth so the threads spread over different iterations
+        let first_depth = 1 + self.helper_id % 2;
+
+        for search_depth in first_depth..=limits.max_depth().min(MAX_PLY - 1) {
+            let lines = match self.search_root_lines(board, search_depth) {
+                Some(lines) => lines,
+                None => break,
+            };
+
+            self.current_iteration_depth = search_depth;
+
+            // Iteration finished cleanly: promote the first line, it was searched without exclusions
+            if let Some(best_line) = lines.first() {
+                self.best_move = EngineMove { choosen_move: best_line.moves[0], eval: best_line.score };
+                self.principal_variation = best_line.moves.clone();
+            }
+            self.multi_pv_lines = lines;
+            if self.print_info {
+                self.print_iteration_info();
+            }
+
+            if self.stop.load(Ordering::Relaxed) || self.mate_limit_reached() || (!self.is_pondering() && !self.time_manager.should_start_iteration()) {
+                break;
+            }
+        }
+        // Stopped before the first iteration finished, any legal move is better than none
+        if self.best_move == NULL_MOVE {
+            let root_moves = generate_all_moves(board, &GenerationMode::All);
+            if root_moves.moves_added > 0 {
+                self.best_move = EngineMove { choosen_move: root_moves.moves[0], eval: 0 };
+            }
+        }
+
+        // UCI forbids sending bestmove during an infinite or ponder search before stop/ponderhit
+        while (self.limits.infinite || self.is_pondering()) && !self.stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Runs a normal search that keeps the best `multi_pv` root moves with exact scores, best line first.
+    pub fn search_multi_pv(&mut self, board: &mut Board, limits: SearchLimits, multi_pv: usize) -> Vec<ScoredLine> {
+        self.multi_pv = multi_pv;
+        self.search(board, limits);
+        self.multi_pv_lines.clone()
+    }
+
+    /// Searches the root once per MultiPV line, each time excluding the root moves of the lines found before.
+    /// Returns None if the iteration was aborted.
+    fn search_root_lines(&mut self, board: &mut Board, search_depth: usize) -> Option<Vec<ScoredLine>> {
+        let mut lines: Vec<ScoredLine> = Vec::new();
+        self.excluded_root_moves.clear();
 
-        for search_depth in 1..255 {
+        for pv_index in 0..self.multi_pv.max(1) {
+            self.pv_index = pv_index;
             self.has_searched_one_move = false;
             self.best_move_this_iteration = NULL_MOVE;
 
-
             let score = self.negamax(board, -100_000_000, 100_000_000, search_depth as i32, 0);
-            if score == Self::SEARCH_ABORTED || self.stop.load(Ordering::Relaxed) {
+            if score == Self::SEARCH_ABORTED {
+                return None;
+            }
+            // No root moves left to search
+            if !self.has_searched_one_move {
                 break;
             }
 
-            self.current_iteration_depth = search_depth;
+            lines.push(ScoredLine { score: self.best_move_this_iteration.eval, moves: self.pv_table.line(0).to_vec() });
+            self.excluded_root_moves.push(self.best_move_this_iteration.choosen_move);
+        }
+        Some(lines)
+    }
 
-            // Iteration finished cleanly: promote best_move_this_iteration
-            if self.has_searched_one_move {
-                self.best_move = self.best_move_this_iteration;
-            }
+    fn print_iteration_info(&self) {
+        let elapsed = self.time_manager.elapsed();
+        let nodes = self.total_nodes();
+        let nps = (nodes as u128 * 1000) / elapsed.as_millis().max(1);
+
+        for (index, line) in self.multi_pv_lines.iter().enumerate() {
+            let pv: String = line.moves.iter().map(|mv| format!(" {}", convert_mv_to_uci(*mv))).collect();
+
+            println!(
+                "info depth {} seldepth {} multipv {} score {} nodes {} time {} nps {} hashfull {} pv{}",
+                self.current_iteration_depth,
+                self.depth.max(self.current_iteration_depth),
+                index + 1,
+                format_score(line.score),
+                nodes,
+                elapsed.as_millis(),
+                nps,
+                self.transposition_table.hashfull(),
+                pv
+            );
+        }
+    }
+
+    /// The second PV move is the reply we expect, if the PV got cut short we fall back to the stored TT move.
+    pub fn ponder_move(&self, board: &mut Board) -> Option<Move> {
+        if self.best_move == NULL_MOVE {
+            return None;
+        }
+        if self.principal_variation.len() >= 2 && self.principal_variation[0] == self.best_move.choosen_move {
+            return Some(self.principal_variation[1]);
+        }
+        let last_mv_info = board.make_move(self.best_move.choosen_move);
+        let ponder_move = self.transposition_table.try_get_stored_move(board.zobrist_hash()).and_then(|mv| board.decode_move(mv));
+        board.undo_move(last_mv_info);
 
+        ponder_move
+    }
 
+    fn is_pondering(&mut self) -> bool {
+        if !self.limits.ponder {
+            return false;
+        }
+        if self.pondering.load(Ordering::Relaxed) {
+            return true;
+        }
+        // ponderhit: our clock starts running now
+        self.limits.ponder = false;
+        self.time_manager.restart();
+        false
+    }
+
+    fn mate_limit_reached(&self) -> bool {
+        match self.limits.mate {
+            Some(mate) => {
+                let plies_to_mate = MATE_VALUE - self.best_move.eval;
+                self.best_move.eval > 0 && (plies_to_mate + 1) / 2 <= mate as i32
+            }
+            None => false,
+        }
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if self.helper_id != 0 && self.nodes - self.published_nodes >= TIME_CHECK_INTERVAL {
+            self.helper_nodes.fetch_add(self.nodes - self.published_nodes, Ordering::Relaxed);
+            self.published_nodes = self.nodes;
+        }
+        if self.stop.load(Ordering::Relaxed) {
+            return true;
+        }
+        if self.is_pondering() {
+            return false;
         }
+        let node_limit_reached = self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes);
+        let time_limit_reached = self.nodes.is_multiple_of(TIME_CHECK_INTERVAL) && self.time_manager.hard_limit_reached();
+        if node_limit_reached || time_limit_reached {
+            self.stop.store(true, Ordering::Relaxed);
+            return true;
+        }
+        false
     }
 
     const SEARCH_ABORTED: i32 = 1198680429; //Grim converted to Number
     pub fn negamax(&mut self, board: &mut Board, alpha: i32, beta: i32, depth_left: i32, depth_from_root: usize) -> i32 {
-        if self.stop.load(Ordering::Relaxed) {
+        let after_null_move = std::mem::replace(&mut self.after_null_move, false);
+        if self.should_stop() {
             return Self::SEARCH_ABORTED;
         }
+        self.pv_table.reset(depth_from_root);
 
         let zobrist_hash = board.zobrist_hash();
+        if depth_from_root > 0 && (board.has_insufficient_material() || board.is_repetition_in_search(zobrist_hash, depth_from_root)) {
+            return 0;
+        }
+
+        // No TT cutoffs at the root, we need the full PV there
         let transposition_value = self.transposition_table.lookup_evaluation(zobrist_hash, depth_left, depth_from_root as i32, alpha, beta);
-        if transposition_value != LOOKUP_FAILED {
-            if depth_from_root == 0 {
-                let index = self.transposition_table.index(zobrist_hash);
-                self.best_move_this_iteration = EngineMove{ choosen_move: self.transposition_table.try_get_stored_move(zobrist_hash).unwrap(), eval: self.transposition_table.entries[index].value };
-                self.has_searched_one_move = true;
-            }
+        if transposition_value != LOOKUP_FAILED && depth_from_root > 0 {
             return transposition_value;
         }
 
-        if depth_left == 0 {
-            return self.quiescence(board, alpha, beta, depth_from_root);
+        if depth_left == 0 || depth_from_root >= MAX_PLY - 1 {
+            return self.quiescence(board, alpha, beta, depth_from_root, true);
         }
+        self.nodes += 1;
 
-        let mut move_list = generate_all_moves(board, &GenerationMode::All);
-        let board_gamestate = board.game_state(&move_list);
-
-
-        match board_gamestate {
-            GameState::WhiteWin | GameState::BlackWin => {
-                return -MATE_VALUE + depth_from_root as i32;
+        // Everything outside the principal variation is searched with a null window
+        let pv_node = beta - alpha > 1;
+        let in_check = board.in_check();
+        if depth_from_root > 0 && !pv_node && !after_null_move && self.null_move_allowed && depth_left >= NULL_MOVE_MIN_DEPTH
+            && beta.abs() < MATE_VALUE - MAX_PLY as i32 && !in_check && !board.has_only_pawns() && evaluate_board(board) >= beta
+        {
+            if let Some(score) = self.null_move_cutoff(board, beta, depth_left, depth_from_root) {
+                return score;
             }
-            GameState::Draw => {
-                return 0;
-            }
-            _ => {}
         }
-        let pv_move = if depth_from_root == 0 {
-            Some(self.best_move.choosen_move)
+
+        // Mate and stalemate only show once the moves run out, but a mate still beats the fifty move rule.
+        // Repetitions and insufficient material were handled above, the root is searched even in a drawn position
+        if depth_from_root > 0 && board.is_fifty_move_draw() && !(in_check && generate_all_moves(board, &GenerationMode::All).moves_added == 0) {
+            return 0;
+        }
+
+        let tt_move = if depth_from_root == 0 {
+            self.multi_pv_lines.get(self.pv_index).map(|line| line.moves[0])
         } else {
-            self.transposition_table.try_get_stored_move(zobrist_hash)
+            self.transposition_table.try_get_stored_move(zobrist_hash).and_then(|mv| board.decode_move(mv))
         };
-
-        move_list.order_moves(pv_move);
+        let previous_move = if depth_from_root > 0 { self.current_line[depth_from_root - 1] } else { None };
+        // With root moves excluded the root score isn't the score of the position, so it must not go into the TT
+        let store_in_tt = depth_from_root > 0 || self.excluded_root_moves.is_empty();
+        let mut move_picker = MovePicker::new(tt_move, self.history.killers(depth_from_root), depth_from_root, previous_move);
         let mut alpha = alpha;
 
         let mut evaluation_bound = UpperBound;
         let mut best_move_this_position = None;
+        let mut legal_moves = 0;
+        let mut moves_searched = 0;
+        let mut tried_quiets = [NULL_MOVE.choosen_move; MAX_TRIED_QUIETS];
+        let mut tried_quiets_count = 0;
+
+        while let Some(mv) = move_picker.next(board, &self.history) {
+            legal_moves += 1;
+            if depth_from_root == 0 && self.excluded_root_moves.contains(&mv) {
+                continue;
+            }
+            let quiet = mv.capture == NoPiece && mv.promotion == NoPiece;
+            if !pv_node && !in_check && moves_searched > 0 && depth_left <= SEE_PRUNING_DEPTH {
+                let margin = if quiet { SEE_QUIET_MARGIN } else { SEE_CAPTURE_MARGIN };
+                if !board.see_ge(mv, -margin * depth_left) && !gives_check(board, &mv) {
+                    continue;
+                }
+            }
+            let reduce = quiet && !in_check && depth_left >= LMR_MIN_DEPTH && moves_searched >= LMR_FULL_DEPTH_MOVES
+                && !self.history.is_killer(depth_from_root, mv) && !gives_check(board, &mv);
+            self.current_line[depth_from_root] = Some(mv);
+            let last_mv_info = board.make_move(mv);
+
+            // PVS: only the first move gets the full window, the rest just have to prove they are no better.
+            // Late quiet moves are tried at reduced depth first and searched again if they beat alpha anyway.
+            let eval = if moves_searched == 0 {
+                -self.negamax(board, -beta, -alpha, depth_left - 1, depth_from_root + 1)
+            } else {
+                let reduction = if reduce { LMR_REDUCTIONS[depth_left.min(63) as usize][moves_searched.min(63)].clamp(0, depth_left - 2) } else { 0 };
+                let mut eval = -self.negamax(board, -alpha - 1, -alpha, depth_left - 1 - reduction, depth_from_root + 1);
+                if eval > alpha && reduction > 0 {
+                    eval = -self.negamax(board, -alpha - 1, -alpha, depth_left - 1, depth_from_root + 1);
+                }
+                if eval > alpha && eval < beta {
+                    eval = -self.negamax(board, -beta, -alpha, depth_left - 1, depth_from_root + 1);
+                }
+                eval
+            };
+            board.undo_move(last_mv_info);
+            moves_searched += 1;
 
-        for i in 0..move_list.moves_added {
-            let last_mv_info = board.make_move(move_list.moves[i]);
-
-            let eval = -self.negamax(board, -beta, -alpha, depth_left - 1, depth_from_root +1);
             if eval == Self::SEARCH_ABORTED || eval == -Self::SEARCH_ABORTED {
                 return Self::SEARCH_ABORTED; // propagate it up
             }
 
-            board.undo_move(last_mv_info);
-
             if eval >= beta {
-                self.transposition_table.store_evaluation(zobrist_hash, depth_left, depth_from_root as i32, beta, LowerBound, Some(move_list.moves[i]));
+                if store_in_tt {
+                    self.transposition_table.store_evaluation(zobrist_hash, depth_left, depth_from_root as i32, beta, LowerBound, Some(mv));
+                }
+                if quiet {
+                    self.history.update(mv, depth_from_root, board.white_to_move, previous_move, depth_left, &tried_quiets[..tried_quiets_count]);
+                }
 
                 return beta; // Beta cut-off
             }
 
+            if quiet && tried_quiets_count < MAX_TRIED_QUIETS {
+                tried_quiets[tried_quiets_count] = mv;
+                tried_quiets_count += 1;
+            }
+
             if eval > alpha {
                 evaluation_bound = Exact;
-                best_move_this_position = Some(move_list.moves[i]);
+                best_move_this_position = Some(mv);
+                self.pv_table.update(depth_from_root, mv);
 
                 alpha = eval;
 
                 if depth_from_root == 0 {
-                    self.best_move_this_iteration = EngineMove {choosen_move: move_list.moves[i], eval };
+                    self.best_move_this_iteration = EngineMove {choosen_move: mv, eval };
                     self.has_searched_one_move = true;
                 }
             }
         }
-        self.transposition_table.store_evaluation(zobrist_hash, depth_left, depth_from_root as i32, alpha, evaluation_bound, best_move_this_position);
+        if legal_moves == 0 {
+            return if in_check { -MATE_VALUE + depth_from_root as i32 } else { 0 };
+        }
+        if store_in_tt {
+            self.transposition_table.store_evaluation(zobrist_hash, depth_left, depth_from_root as i32, alpha, evaluation_bound, best_move_this_position);
+        }
 
         alpha
     }
 
-    fn quiescence(&mut self, board: &mut Board, mut alpha: i32, beta: i32, depth_from_ply: usize) -> i32 {
-        if self.stop.load(Ordering::Relaxed) {
+    /// Gives the opponent a free move. If a reduced search still fails high, the position is good enough
+    /// to cut without searching our moves. Returns the score to return from the node, or None to search normally.
+    fn null_move_cutoff(&mut self, board: &mut Board, beta: i32, depth_left: i32, depth_from_root: usize) -> Option<i32> {
+        let reduction = 3 + depth_left / 6;
+
+        self.current_line[depth_from_root] = None;
+        let last_mv_info = board.make_null_move();
+        self.after_null_move = true;
+        let score = -self.negamax(board, -beta, -beta + 1, (depth_left - 1 - reduction).max(0), depth_from_root + 1);
+        board.undo_null_move(last_mv_info);
+
+        if score == Self::SEARCH_ABORTED || score == -Self::SEARCH_ABORTED {
+            return Some(Self::SEARCH_ABORTED);
+        }
+        if score < beta {
+            return None;
+        }
+
+        if depth_left >= NULL_MOVE_VERIFICATION_DEPTH {
+            self.null_move_allowed = false;
+            let verified = self.negamax(board, beta - 1, beta, depth_left - reduction, depth_from_root);
+            self.null_move_allowed = true;
+
+            if verified == Self::SEARCH_ABORTED {
+                return Some(Self::SEARCH_ABORTED);
+            }
+            if verified < beta {
+                return None;
+            }
+        }
+        // A mate found after passing isn't proven, so don't return the score itself
+        Some(beta)
+    }
+
+    /// Searches captures until the position is quiet. The first ply also tries the quiet checks, and a side
+    /// in check can't stand pat, it has to search all its evasions.
+    fn quiescence(&mut self, board: &mut Board, mut alpha: i32, beta: i32, depth_from_ply: usize, quiet_checks: bool) -> i32 {
+        if self.should_stop() {
             return Self::SEARCH_ABORTED;
         }
+        if board.has_insufficient_material() {
+            return 0;
+        }
 
-        let mut move_list = generate_all_moves(board, &GenerationMode::Capture);
-        let eval = evaluate_board(&board);
         self.nodes += 1;
         if depth_from_ply > self.depth {
             self.depth = depth_from_ply;
         }
-        if eval >= beta {
-            return beta;
-        }
-        if eval > alpha {
-            alpha = eval;
+        let in_check = board.in_check();
+        if !in_check {
+            let eval = evaluate_board(board);
+            if eval >= beta {
+                return beta;
+            }
+            if eval > alpha {
+                alpha = eval;
+            }
         }
 
-        move_list.order_moves(None);
-
-        for i in 0..move_list.moves_added {
-            let last_mv_info = board.make_move(move_list.moves[i]);
-            let score = -self.quiescence(board, -beta, -alpha, depth_from_ply+1);
+        let mut move_picker = match (in_check, quiet_checks) {
+            (true, _) => MovePicker::evasions(),
+            (false, true) => MovePicker::captures_and_checks(),
+            (false, false) => MovePicker::captures(),
+        };
+        let mut legal_moves = 0;
+        while let Some(mv) = move_picker.next(board, &self.history) {
+            legal_moves += 1;
+            let last_mv_info = board.make_move(mv);
+            let score = -self.quiescence(board, -beta, -alpha, depth_from_ply+1, false);
             board.undo_move(last_mv_info);
 
+            if score == Self::SEARCH_ABORTED || score == -Self::SEARCH_ABORTED {
+                return Self::SEARCH_ABORTED;
+            }
+
             if score >= beta {
                 return beta;
             }
@@ -180,6 +566,36 @@ impl Searcher {
                 alpha = score;
             }
         }
+        if in_check && legal_moves == 0 {
+            return -MATE_VALUE + depth_from_ply as i32;
+        }
         return alpha
     }
-}
\ No newline at end of file
+}
+
+/// Formats an evaluation as UCI score, mate scores are given in moves instead of plies.
+pub fn format_score(eval: i32) -> String {
+    if eval.abs() > MATE_VALUE - MAX_PLY as i32 {
+        let plies_to_mate = MATE_VALUE - eval.abs();
+        let moves_to_mate = (plies_to_mate + 1) / 2;
+        format!("mate {}", if eval > 0 { moves_to_mate } else { -moves_to_mate })
+    } else {
+        format!("cp {}", eval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Searcher;
+    use crate::fen_import::make_board;
+    use crate::search::pv_table::MAX_PLY;
+
+    #[test]
+    fn quiescence_past_max_ply_searches_evasions() {
+        // Ra8+ is a quiet check at the last ply, the reply in check is searched one ply beyond MAX_PLY
+        let mut board = make_board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        let mut searcher = Searcher::new();
+        let score = searcher.negamax(&mut board, -100_000_000, 100_000_000, 1, MAX_PLY - 1);
+        assert!(score > 0);
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+const MOVE_OVERHEAD_MS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct SearchLimits {
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u64>,
+    pub movetime: Option<u64>,
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    pub mate: Option<u32>,
+    pub infinite: bool,
+    pub ponder: bool,
+}
+
+impl SearchLimits {
+    pub fn infinite() -> Self {
+        SearchLimits { infinite: true, ..Default::default() }
+    }
+
+    pub fn max_depth(&self) -> usize {
+        match (self.depth, self.mate) {
+            (Some(depth), _) => depth.max(1),
+            (None, Some(mate)) => (2 * mate as usize).saturating_sub(1).max(1),
+            (None, None) => 254,
+        }
+    }
+}
+
+/// Soft limit: don't start a new iteration after it. Hard limit: abort the running iteration.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+}
+
+impl TimeManager {
+    pub fn new(limits: &SearchLimits, white_to_move: bool) -> Self {
+        let (soft_limit, hard_limit) = Self::allocate(limits, white_to_move);
+        TimeManager { start: Instant::now(), soft_limit, hard_limit }
+    }
+
+    fn allocate(limits: &SearchLimits, white_to_move: bool) -> (Option<Duration>, Option<Duration>) {
+        if limits.infinite {
+            return (None, None);
+        }
+        if let Some(movetime) = limits.movetime {
+            let time = Duration::from_millis(movetime.saturating_sub(MOVE_OVERHEAD_MS).max(1));
+            return (Some(time), Some(time));
+        }
+
+        let (time_left, increment) = if white_to_move {
+            (limits.wtime, limits.winc.unwrap_or(0))
+        } else {
+            (limits.btime, limits.binc.unwrap_or(0))
+        };
+        let time_left = match time_left {
+            Some(time_left) => time_left,
+            None => return (None, None),
+        };
+
+        let usable = time_left.saturating_sub(MOVE_OVERHEAD_MS).max(1);
+        let moves_to_go = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).clamp(1, DEFAULT_MOVES_TO_GO);
+
+        let soft = (usable / moves_to_go + increment * 3 / 4).min(usable);
+        // Never spend more than a fifth of the clock on a single move unless it is the last one before the time control
+        let hard_cap = if moves_to_go == 1 { usable } else { usable / 5 };
+        let hard = (soft * 4).min(hard_cap).max(soft);
+
+        (Some(Duration::from_millis(soft.max(1))), Some(Duration::from_millis(hard.max(1))))
+    }
+
+    pub fn restart(&mut self) {
+        self.start = Instant::now();
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn should_start_iteration(&self) -> bool {
+        match self.soft_limit {
+            Some(soft_limit) => self.elapsed() < soft_limit,
+            None => true,
+        }
+    }
+
+    pub fn hard_limit_reached(&self) -> bool {
+        match self.hard_limit {
+            Some(hard_limit) => self.elapsed() >= hard_limit,
+            None => false,
+        }
+    }
+}
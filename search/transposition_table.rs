@@ -1,11 +1,12 @@
 use crate::move_gen_dir::move_gen::Move;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct TranspositionTableEntry {
     pub key: u64,
     pub value: i32,
-    pub mv: Option<Move>,
+    pub mv: Option<u16>,
     pub depth: i32,
     pub node_type: NodeType,
 }
@@ -17,12 +18,35 @@ impl TranspositionTableEntry {
             value,
             depth,
             node_type,
-            mv,
+            mv: mv.map(|mv| mv.to_u16()),
         }
     }
 
     pub fn size_of() -> usize {
-        size_of::<TranspositionTableEntry>()
+        size_of::<AtomicEntry>()
+    }
+
+    // value: bits 0-31, depth: 32-39, node type: 40-41, move: 42-57
+    fn pack(&self) -> u64 {
+        (self.value as u32 as u64)
+            | ((self.depth.clamp(0, 255) as u64) << 32)
+            | ((self.node_type as u64) << 40)
+            | ((self.mv.unwrap_or(0) as u64) << 42)
+    }
+
+    fn unpack(key: u64, data: u64) -> Self {
+        let mv = ((data >> 42) & 0xffff) as u16;
+        TranspositionTableEntry {
+            key,
+            value: data as u32 as i32,
+            depth: ((data >> 32) & 0xff) as i32,
+            node_type: match (data >> 40) & 0b11 {
+                0 => NodeType::Exact,
+                1 => NodeType::LowerBound,
+                _ => NodeType::UpperBound,
+            },
+            mv: if mv == 0 { None } else { Some(mv) },
+        }
     }
 }
 pub const LOOKUP_FAILED:i32 = -1;
@@ -33,9 +57,17 @@ pub enum NodeType {
     UpperBound,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// Lockless slot: the key is stored XORed with the data, so a slot torn by two threads writing
+/// at the same time no longer verifies and is treated as a miss.
+#[derive(Debug, Default)]
+struct AtomicEntry {
+    key_xor_data: AtomicU64,
+    data: AtomicU64,
+}
+
+#[derive(Debug)]
 pub struct TranspositionTable {
-    pub entries: Vec<TranspositionTableEntry>,
+    entries: Vec<AtomicEntry>,
     pub count: u64,
 }
 
@@ -43,41 +75,46 @@ impl TranspositionTable {
     pub fn new(size_mb: usize) -> Self {
         let entry_size = TranspositionTableEntry::size_of();
         let desired_bytes = size_mb * 1024 * 1024;
-        let num_entries = desired_bytes / entry_size;
+        let num_entries = (desired_bytes / entry_size).max(1);
 
         TranspositionTable {
-            entries: vec![
-                TranspositionTableEntry {
-                    key: 0,
-                    value: 0,
-                    depth: 0,
-                    node_type: NodeType::Exact,
-                    mv: None
-                };
-                num_entries
-            ],
+            entries: (0..num_entries).map(|_| AtomicEntry::default()).collect(),
             count: num_entries as u64,
         }
     }
 
-    pub fn clear(&mut self) {
-        for e in self.entries.iter_mut() {
-            *e = TranspositionTableEntry {
-                key: 0,
-                value: 0,
-                depth: 0,
-                node_type: NodeType::Exact,
-                mv: None,
-            };
+    pub fn clear(&self) {
+        for e in self.entries.iter() {
+            e.key_xor_data.store(0, Ordering::Relaxed);
+            e.data.store(0, Ordering::Relaxed);
         }
     }
 
+    /// Permille of used entries, estimated from the first thousand slots.
+    pub fn hashfull(&self) -> usize {
+        let sample = self.entries.len().min(1000);
+        let used = self.entries[..sample].iter().filter(|entry| entry.data.load(Ordering::Relaxed) != 0).count();
+        used * 1000 / sample
+    }
+
     pub fn index(&self, zobrist_key: u64) -> usize {
         (zobrist_key % self.count) as usize
     }
 
-    pub fn try_get_stored_move(&self, zobrist_key: u64) -> Option<Move> {
-        self.entries[self.index(zobrist_key)].mv
+    fn probe(&self, zobrist_key: u64) -> Option<TranspositionTableEntry> {
+        let slot = &self.entries[self.index(zobrist_key)];
+        let data = slot.data.load(Ordering::Relaxed);
+        let key_xor_data = slot.key_xor_data.load(Ordering::Relaxed);
+
+        if key_xor_data ^ data == zobrist_key {
+            Some(TranspositionTableEntry::unpack(zobrist_key, data))
+        } else {
+            None
+        }
+    }
+
+    pub fn try_get_stored_move(&self, zobrist_key: u64) -> Option<u16> {
+        self.probe(zobrist_key).and_then(|entry| entry.mv)
     }
 
     pub fn lookup_evaluation(
@@ -88,8 +125,7 @@ impl TranspositionTable {
         alpha: i32,
         beta: i32,
     ) -> i32 {
-        let entry = &self.entries[self.index(zobrist_key)];
-        if entry.key == zobrist_key {
+        if let Some(entry) = self.probe(zobrist_key) {
             if entry.depth >= depth {
                 let corrected_score =
                     Self::correct_retrieved_mate_score(entry.value, ply_from_root);
@@ -105,7 +141,7 @@ impl TranspositionTable {
     }
 
     pub fn store_evaluation(
-        &mut self,
+        &self,
         zobrist_key: u64,
         depth: i32,
         ply_from_root: i32,
@@ -113,9 +149,12 @@ impl TranspositionTable {
         eval_type: NodeType,
         mv: Option<Move>,
     ) {
-        let index = self.index(zobrist_key);
+        let slot = &self.entries[self.index(zobrist_key)];
         let corrected = Self::correct_mate_score_for_storage(eval, ply_from_root);
-        self.entries[index] = TranspositionTableEntry::new(zobrist_key, corrected, depth, eval_type, mv);
+        let data = TranspositionTableEntry::new(zobrist_key, corrected, depth, eval_type, mv).pack();
+
+        slot.key_xor_data.store(zobrist_key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
     }
 
     fn correct_mate_score_for_storage(score: i32, num_ply_searched: i32) -> i32 {
@@ -141,7 +180,7 @@ impl TranspositionTable {
         score.abs() > 9_000_000
     }
 
-    pub fn get_entry(&self, zobrist_key: u64) -> &TranspositionTableEntry {
-        &self.entries[self.index(zobrist_key)]
+    pub fn get_entry(&self, zobrist_key: u64) -> Option<TranspositionTableEntry> {
+        self.probe(zobrist_key)
     }
-}
\ No newline at end of file
+}
@@ -1,17 +1,18 @@
-use crate::fen_import::{make_board, start_pos};
-use crate::helpers::{index_to_sq, sq_to_index};
-use crate::move_gen_dir::move_gen::{GenerationMode, Move, PieceType, Square};
-use crate::search::search::{Searcher, NULL_MOVE};
+use crate::fen_import::start_pos;
+use crate::helpers::index_to_sq;
+use crate::move_gen_dir::move_gen::{GenerationMode, Move, PieceType};
+use crate::move_gen_dir::perft::print_divide;
+use crate::search::search::{EngineMove, Searcher, NULL_MOVE};
 use crate::{generate_all_moves, Board};
 use std::{io, thread};
 use std::io::Write;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread::JoinHandle;
-use std::time::Duration;
-use crate::OpeningBook::work_with_opening_book::find_opening_move;
+use crate::search::time_manager::SearchLimits;
+use crate::OpeningBook::polyglot::BookSelection;
+use crate::uci_options::{parse_setoption, EngineOptions, OptionValue, UCI_OPTIONS};
 
-fn convert_mv_to_uci(mv: Move) -> String {
+pub fn convert_mv_to_uci(mv: Move) -> String {
     let mut move_str: String = "".to_owned();
 
     let start_sq =  index_to_sq(mv.start_square.trailing_zeros() as usize);
@@ -34,12 +35,18 @@ fn convert_mv_to_uci(mv: Move) -> String {
 }
 
 
-pub fn uci_loop() {
+pub fn uci_loop(mut options: EngineOptions) {
     let mut board = start_pos();
 
     let searcher = Arc::new(Mutex::new(Searcher::new()));
     let mut search_thread: Option<std::thread::JoinHandle<()>> = None;
 
+    // The search thread holds the searcher lock, so keep our own handles to the flags
+    let (stop_flag, ponder_flag) = {
+        let s = searcher.lock().unwrap();
+        (s.stop.clone(), s.pondering.clone())
+    };
+
     loop {
         let mut input = String::new();
         if io::stdin().read_line(&mut input).is_err() {
@@ -50,29 +57,48 @@ pub fn uci_loop() {
         if input == "uci" {
             println!("id name bismuth");
             println!("id author lordgrim");
+            for option in UCI_OPTIONS.iter() {
+                println!("{}", options.to_uci_string(option));
+            }
             println!("uciok");
             io::stdout().flush().unwrap();
         }
         else if input == "isready" {
+            // Loading a big book can take a moment, GUIs expect that to happen before readyok
+            options.opening_book();
             println!("readyok");
             io::stdout().flush().unwrap();
         }
+        else if input.starts_with("setoption") {
+            stop_search(&stop_flag, &mut search_thread);
+            setoption_command(input, &searcher, &mut options);
+            io::stdout().flush().unwrap();
+        }
         else if input.starts_with("position") {
             position_command(&mut board, input);
             io::stdout().flush().unwrap();
         }
+        else if input.starts_with("go perft") {
+            stop_search(&stop_flag, &mut search_thread);
+            match input.split_whitespace().nth(2).and_then(|depth| depth.parse::<usize>().ok()) {
+                Some(depth) => print_divide(&mut board, depth),
+                None => println!("info string go perft needs a depth"),
+            }
+            io::stdout().flush().unwrap();
+        }
         else if input.starts_with("go") {
-            go_command(&mut board, &searcher, &mut search_thread);
+            stop_search(&stop_flag, &mut search_thread);
+            go_command(&mut board, input, &mut options, &searcher, &mut search_thread);
             io::stdout().flush().unwrap();
         }
         else if input == "stop" {
-            // stop_flag.store(true, Ordering::Relaxed);
-            //
-            // if let Some(handle) = search_thread.take() {
-            //     let _ = handle.join(); // wait for search to finish cleanly
-            // }
+            stop_search(&stop_flag, &mut search_thread);
+        }
+        else if input == "ponderhit" {
+            ponder_flag.store(false, Ordering::SeqCst);
         }
         else if input == "quit" {
+            stop_search(&stop_flag, &mut search_thread);
             break;
         }
     }
@@ -80,105 +106,168 @@ pub fn uci_loop() {
 
 
 
+fn stop_search(stop_flag: &Arc<AtomicBool>, search_thread: &mut Option<std::thread::JoinHandle<()>>) {
+    stop_flag.store(true, Ordering::SeqCst);
+
+    if let Some(handle) = search_thread.take() {
+        let _ = handle.join(); // wait for search to finish cleanly, it prints bestmove itself
+    }
+}
+
+fn print_bestmove(best_move: EngineMove, ponder_move: Option<Move>) {
+    if best_move == NULL_MOVE {
+        println!("bestmove 0000");
+    } else if let Some(ponder_move) = ponder_move {
+        println!("bestmove {} ponder {}", convert_mv_to_uci(best_move.choosen_move), convert_mv_to_uci(ponder_move));
+    } else {
+        println!("bestmove {}", convert_mv_to_uci(best_move.choosen_move));
+    }
+}
+
 fn position_command(board: &mut Board, input: &str) {
     let parts: Vec<&str> = input.split_whitespace().collect();
     if parts.len() >= 2 {
-        *board = if parts[1] == "startpos" {
-            start_pos()
-        } else if parts[1] == "fen" {
-            let fen = parts[2..6].join(" ");
-            make_board(&fen)
+        let moves_idx = parts.iter().position(|&x| x == "moves");
+
+        // A bad FEN keeps the old position instead of taking the engine down
+        let mut new_board = if parts[1] == "fen" {
+            let fen_end = moves_idx.unwrap_or(parts.len());
+            match Board::from_fen(&parts[2.min(fen_end)..fen_end].join(" ")) {
+                Ok(new_board) => new_board,
+                Err(error) => {
+                    println!("info string invalid fen: {}", error);
+                    return;
+                }
+            }
         } else {
             start_pos()
         };
-        if let Some(idx) = parts.iter().position(|&x| x == "moves") {
+        if let Some(idx) = moves_idx {
             for mv_str in &parts[idx + 1..] {
-                convert_uci_to_internal(board, &mv_str);
+                if !convert_uci_to_internal(&mut new_board, mv_str) {
+                    println!("info string illegal move {}, ignoring the rest of the line", mv_str);
+                    break;
+                }
             }
         }
+        *board = new_board;
     }
 }
 
-fn convert_uci_to_internal(mut board: &mut Board, input: &str) {
-    let start_sq_str: Square = *&input[..2].to_uppercase().parse().unwrap();
-    let end_sp_str: Square = *&input[2..4].to_uppercase().parse().unwrap();
-
-    let start_sq: u64 = 1<<sq_to_index(start_sq_str);
-    let end_sq: u64 = 1<<sq_to_index(end_sp_str);
-
-    let all_possible_moves = generate_all_moves(&mut board, &GenerationMode::All);
+fn convert_uci_to_internal(board: &mut Board, input: &str) -> bool {
+    let input = input.to_lowercase();
+    let all_possible_moves = generate_all_moves(board, &GenerationMode::All);
 
     for moves in 0..all_possible_moves.moves_added {
         let move_from_idx = all_possible_moves.moves[moves];
 
-        if move_from_idx.start_square == start_sq && move_from_idx.end_square == end_sq {
-            if move_from_idx.promotion == PieceType::NoPiece {
-                board.make_move(move_from_idx);
-                break;
-            }
-            if input.len() == 5 {
-                let promotion = &input[4..5];
-                match promotion {
-                    "q" => { if move_from_idx.promotion == PieceType::Queen { board.make_move(move_from_idx); } }
-                    "n" => { if move_from_idx.promotion == PieceType::Knight { board.make_move(move_from_idx); } }
-                    "b" => { if move_from_idx.promotion == PieceType::Bishop { board.make_move(move_from_idx); } }
-                    "r" => { if move_from_idx.promotion == PieceType::Rook { board.make_move(move_from_idx); } }
-                    _ => {}
-                }
-            }
+        if convert_mv_to_uci(move_from_idx) == input {
+            board.make_move(move_from_idx);
+            return true;
+        }
+    }
+    false
+}
+
+fn parse_go_command(input: &str) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut tokens = input.split_whitespace().skip(1);
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "wtime" => limits.wtime = parse_next_time(&mut tokens),
+            "btime" => limits.btime = parse_next_time(&mut tokens),
+            "winc" => limits.winc = parse_next_value(&mut tokens),
+            "binc" => limits.binc = parse_next_value(&mut tokens),
+            "movestogo" => limits.movestogo = parse_next_value(&mut tokens),
+            "movetime" => limits.movetime = parse_next_value(&mut tokens),
+            "depth" => limits.depth = parse_next_value(&mut tokens),
+            "nodes" => limits.nodes = parse_next_value(&mut tokens),
+            "mate" => limits.mate = parse_next_value(&mut tokens),
+            "infinite" => limits.infinite = true,
+            "ponder" => limits.ponder = true,
+            _ => {}
         }
     }
+    limits
 }
 
-fn go_command(board: &mut Board, searcher: &Arc<Mutex<Searcher>>, search_thread: &mut Option<std::thread::JoinHandle<()>>) {
+fn parse_next_value<'a, T: std::str::FromStr>(tokens: &mut impl Iterator<Item = &'a str>) -> Option<T> {
+    tokens.next().and_then(|value| value.parse().ok())
+}
+
+fn parse_next_time<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Option<u64> {
+    // Some GUIs send negative clock values once the time is used up
+    parse_next_value::<i64>(tokens).map(|time| time.max(0) as u64)
+}
+
+fn setoption_command(input: &str, searcher: &Arc<Mutex<Searcher>>, options: &mut EngineOptions) {
+    let (option, value) = match parse_setoption(input) {
+        Ok(parsed) => parsed,
+        Err(error) => {
+            println!("info string {}", error);
+            return;
+        }
+    };
+
+    match (option.name, value) {
+        ("Hash", OptionValue::Spin(size_mb)) => searcher.lock().unwrap().resize_hash(size_mb as usize),
+        ("Threads", OptionValue::Spin(threads)) => searcher.lock().unwrap().set_threads(threads as usize),
+        ("Clear Hash", OptionValue::Button) => searcher.lock().unwrap().clear_hash(),
+        ("MultiPV", OptionValue::Spin(multi_pv)) => searcher.lock().unwrap().multi_pv = multi_pv as usize,
+        ("OwnBook", OptionValue::Check(own_book)) => options.own_book = own_book,
+        ("BookFile", OptionValue::String(book_file)) => options.set_book_file(book_file),
+        ("BookMode", OptionValue::String(mode)) => options.book_selection = BookSelection::from_name(&mode).unwrap_or(options.book_selection),
+        ("BookMaxPly", OptionValue::Spin(max_ply)) => options.book_max_ply = max_ply as usize,
+        ("BookSeed", OptionValue::Spin(seed)) => options.set_book_seed(seed as u64),
+        _ => {}
+    }
+}
+
+fn go_command(board: &mut Board, input: &str, options: &mut EngineOptions, searcher: &Arc<Mutex<Searcher>>, search_thread: &mut Option<std::thread::JoinHandle<()>>) {
+    let limits = parse_go_command(input);
+
     // Clone board for the search thread
     let mut board_clone = board.clone();
 
-    let opening_move = find_opening_move(board);
-
-    if opening_move.is_some(){
-        println!("bestmove {}", convert_mv_to_uci(opening_move.unwrap()));
-        return;
+    // A book move would have to be held back until ponderhit anyway, so ponder searches skip the book
+    if !limits.ponder {
+        if let Some(opening_move) = options.book_move(board) {
+            println!("bestmove {}", convert_mv_to_uci(opening_move));
+            return;
+        }
     }
 
-    // Extract a clone of the stop flag (fast, brief lock)
-    let stop_flag: Arc<std::sync::atomic::AtomicBool> = {
+    // Extract a clone of the flags (fast, brief lock)
+    let (stop_flag, ponder_flag) = {
         let s = searcher.lock().unwrap();
-        s.stop.clone()
+        (s.stop.clone(), s.pondering.clone())
     };
 
-    // Clear stop flag without holding the big mutex
+    // Reset the flags without holding the big mutex
     stop_flag.store(false, Ordering::SeqCst);
+    ponder_flag.store(limits.ponder, Ordering::SeqCst);
 
     // Spawn the search thread (it may lock the searcher for mutation as before)
     let searcher_for_thread = Arc::clone(searcher);
     *search_thread = Some(thread::spawn(move || {
         {
             // lock briefly to call iterative_deepening - this will hold the mutex while searching
-            // that's OK because the timer doesn't need the mutex anymore (it has stop_flag).
+            // that's OK because the time limits are checked by the searcher itself.
             let mut s = searcher_for_thread.lock().unwrap();
-            s.iterative_deepening(&mut board_clone);
+            s.search(&mut board_clone, limits);
         }
 
         // after search completes, read the results under lock
-        let (best, positions, depth) = {
+        let (best, ponder) = {
             let s = searcher_for_thread.lock().unwrap();
-            (s.best_move, s.nodes, s.depth)
+            (s.best_move, s.ponder_move(&mut board_clone))
         };
-        println!("info score {} nodes {positions} depth {depth}", best.eval);
-        println!("bestmove {}", convert_mv_to_uci(best.choosen_move));
+        print_bestmove(best, ponder);
 
         let mut s = searcher_for_thread.lock().unwrap();
         s.depth = 0;
         s.nodes = 0;
         io::stdout().flush().unwrap();
     }));
-
-    // Spawn a *timer* thread that uses the cloned stop_flag (no mutex lock)
-    let stop_for_timer = stop_flag.clone();
-    thread::spawn(move || {
-        thread::sleep(Duration::from_millis(100));
-        // set stop using the atomic directly (no mutex locking)
-        stop_for_timer.store(true, Ordering::SeqCst);
-    });
 }
\ No newline at end of file
@@ -0,0 +1,261 @@
+use crate::move_gen_dir::move_gen::Move;
+use crate::Board;
+use crate::OpeningBook::polyglot::BookSelection;
+use crate::OpeningBook::work_with_opening_book::OpeningBook;
+use rand::{Rng, SeedableRng, StdRng};
+
+pub const DEFAULT_HASH_MB: usize = 128;
+pub const DEFAULT_BOOK_FILE: &str = "OpeningBook/book.bin";
+pub const MAX_MULTI_PV: i64 = 218;
+pub const MAX_BOOK_PLY: i64 = 1024;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OptionType {
+    Spin { default: i64, min: i64, max: i64 },
+    Check { default: bool },
+    String { default: &'static str },
+    Combo { default: &'static str, vars: &'static [&'static str] },
+    Button,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct UciOption {
+    pub name: &'static str,
+    pub option_type: OptionType,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum OptionValue {
+    Spin(i64),
+    Check(bool),
+    String(String),
+    Button,
+}
+
+pub const UCI_OPTIONS: [UciOption; 9] = [
+    UciOption { name: "Hash", option_type: OptionType::Spin { default: DEFAULT_HASH_MB as i64, min: 1, max: 65536 } },
+    UciOption { name: "Threads", option_type: OptionType::Spin { default: 1, min: 1, max: 512 } },
+    UciOption { name: "Clear Hash", option_type: OptionType::Button },
+    UciOption { name: "MultiPV", option_type: OptionType::Spin { default: 1, min: 1, max: MAX_MULTI_PV } },
+    UciOption { name: "OwnBook", option_type: OptionType::Check { default: true } },
+    UciOption { name: "BookFile", option_type: OptionType::String { default: DEFAULT_BOOK_FILE } },
+    UciOption { name: "BookMode", option_type: OptionType::Combo { default: "Weighted", vars: &BookSelection::NAMES } },
+    UciOption { name: "BookMaxPly", option_type: OptionType::Spin { default: MAX_BOOK_PLY, min: 0, max: MAX_BOOK_PLY } },
+    UciOption { name: "BookSeed", option_type: OptionType::Spin { default: 0, min: 0, max: i64::MAX } },
+];
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BookState {
+    NotLoaded,
+    Loaded(OpeningBook),
+    /// Loading failed, the warning was already printed.
+    Unavailable,
+}
+
+/// Engine state that is configured through `setoption` but doesn't live inside the searcher.
+#[derive(Debug, Clone)]
+pub struct EngineOptions {
+    pub own_book: bool,
+    pub book_file: String,
+    pub book: BookState,
+    pub book_selection: BookSelection,
+    /// Book moves are only played while fewer plies than this were played in the game.
+    pub book_max_ply: usize,
+    book_rng: StdRng,
+}
+
+impl EngineOptions {
+    pub fn new() -> Self {
+        EngineOptions {
+            own_book: true,
+            book_file: DEFAULT_BOOK_FILE.to_string(),
+            book: BookState::NotLoaded,
+            book_selection: BookSelection::Weighted,
+            book_max_ply: MAX_BOOK_PLY as usize,
+            book_rng: random_book_rng(),
+        }
+    }
+
+    /// A fixed seed makes the book choices repeat from one engine start to the next, 0 picks a random one.
+    pub fn set_book_seed(&mut self, seed: u64) {
+        self.book_rng = if seed == 0 { random_book_rng() } else { StdRng::from_seed(&[seed as usize][..]) };
+    }
+
+    pub fn set_book_file(&mut self, book_file: String) {
+        if book_file != self.book_file {
+            self.book_file = book_file;
+            self.book = BookState::NotLoaded;
+        }
+    }
+
+    /// Loads the book on first use and keeps it. A missing or broken file is reported
+    /// once as `info string`, after that the engine simply searches.
+    pub fn opening_book(&mut self) -> Option<&OpeningBook> {
+        if !self.own_book {
+            return None;
+        }
+        if self.book == BookState::NotLoaded {
+            self.book = match OpeningBook::load(&self.book_file) {
+                Ok(book) => BookState::Loaded(book),
+                Err(error) => {
+                    println!("info string opening book disabled: {}", error);
+                    BookState::Unavailable
+                }
+            };
+        }
+        match &self.book {
+            BookState::Loaded(book) => Some(book),
+            _ => None,
+        }
+    }
+
+    pub fn book_move(&mut self, board: &mut Board) -> Option<Move> {
+        let game_ply = 2 * board.fullmove_number.saturating_sub(1) as usize + !board.white_to_move as usize;
+        if game_ply >= self.book_max_ply {
+            return None;
+        }
+        self.opening_book()?;
+        match &self.book {
+            BookState::Loaded(book) => book.pick_move(board, self.book_selection, &mut self.book_rng),
+            _ => None,
+        }
+    }
+
+    /// The `option` line for `uci`, the book path shows what is currently set so a GUI
+    /// sending back the defaults doesn't undo a `--book` given on the command line.
+    pub fn to_uci_string(&self, option: &UciOption) -> String {
+        match option.option_type {
+            OptionType::String { .. } if option.name == "BookFile" => string_option_line(option.name, &self.book_file),
+            _ => option.to_uci_string(),
+        }
+    }
+}
+
+fn random_book_rng() -> StdRng {
+    StdRng::from_seed(&[rand::thread_rng().gen::<usize>()][..])
+}
+
+fn string_option_line(name: &str, default: &str) -> String {
+    format!("option name {} type string default {}", name, if default.is_empty() { "<empty>" } else { default })
+}
+
+impl UciOption {
+    pub fn to_uci_string(&self) -> String {
+        match self.option_type {
+            OptionType::Spin { default, min, max } => format!("option name {} type spin default {} min {} max {}", self.name, default, min, max),
+            OptionType::Check { default } => format!("option name {} type check default {}", self.name, default),
+            OptionType::String { default } => string_option_line(self.name, default),
+            OptionType::Combo { default, vars } => {
+                let vars: Vec<String> = vars.iter().map(|var| format!("var {}", var)).collect();
+                format!("option name {} type combo default {} {}", self.name, default, vars.join(" "))
+            }
+            OptionType::Button => format!("option name {} type button", self.name),
+        }
+    }
+
+    fn parse_value(&self, value: Option<&str>) -> Result<OptionValue, String> {
+        match (self.option_type, value) {
+            (OptionType::Button, _) => Ok(OptionValue::Button),
+            (_, None) => Err(format!("option {} needs a value", self.name)),
+            (OptionType::Spin { min, max, .. }, Some(value)) => match value.parse::<i64>() {
+                Ok(number) => Ok(OptionValue::Spin(number.clamp(min, max))),
+                Err(_) => Err(format!("option {} expects a number, got {}", self.name, value)),
+            },
+            (OptionType::Check { .. }, Some(value)) => match value.to_lowercase().as_str() {
+                "true" => Ok(OptionValue::Check(true)),
+                "false" => Ok(OptionValue::Check(false)),
+                _ => Err(format!("option {} expects true or false, got {}", self.name, value)),
+            },
+            (OptionType::Combo { vars, .. }, Some(value)) => match vars.iter().find(|var| var.eq_ignore_ascii_case(value)) {
+                Some(var) => Ok(OptionValue::String(var.to_string())),
+                None => Err(format!("option {} expects one of {}, got {}", self.name, vars.join(", "), value)),
+            },
+            (OptionType::String { .. }, Some(value)) => {
+                let value = if value == "<empty>" { "" } else { value };
+                Ok(OptionValue::String(value.to_string()))
+            }
+        }
+    }
+}
+
+pub fn find_option(name: &str) -> Option<&'static UciOption> {
+    // Option names are case insensitive in UCI
+    UCI_OPTIONS.iter().find(|option| option.name.eq_ignore_ascii_case(name))
+}
+
+/// Parses `setoption name <name> [value <value>]`, names and values may contain spaces.
+pub fn parse_setoption(input: &str) -> Result<(&'static UciOption, OptionValue), String> {
+    let tokens: Vec<&str> = input.split_whitespace().collect();
+    let name_idx = tokens.iter().position(|&token| token == "name").ok_or("setoption without name")?;
+    let value_idx = tokens.iter().position(|&token| token == "value");
+
+    let name_end = value_idx.unwrap_or(tokens.len());
+    if name_end <= name_idx + 1 {
+        return Err("setoption without name".to_string());
+    }
+    let name = tokens[name_idx + 1..name_end].join(" ");
+    let value = value_idx.map(|idx| tokens[idx + 1..].join(" "));
+
+    let option = find_option(&name).ok_or(format!("unknown option {}", name))?;
+    let value = option.parse_value(value.as_deref())?;
+    Ok((option, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_setoption, BookState, EngineOptions, OptionValue};
+    use crate::fen_import::start_pos;
+    use crate::OpeningBook::polyglot::{encode_polyglot_move, BookSelection, PolyglotBook, PolyglotEntry};
+    use crate::OpeningBook::work_with_opening_book::OpeningBook;
+
+    #[test]
+    fn missing_book_is_reported_once_and_disabled() {
+        let mut options = EngineOptions::new();
+        options.set_book_file("does/not/exist.bin".to_string());
+        assert!(options.opening_book().is_none());
+        assert_eq!(options.book, BookState::Unavailable);
+        assert!(options.opening_book().is_none());
+
+        // A new path gets another attempt
+        options.set_book_file("also/missing.bin".to_string());
+        assert_eq!(options.book, BookState::NotLoaded);
+
+        options.own_book = false;
+        assert!(options.opening_book().is_none());
+        assert_eq!(options.book, BookState::NotLoaded);
+    }
+
+    #[test]
+    fn book_moves_follow_seed_mode_and_ply_limit() {
+        let mut board = start_pos();
+        let key = board.polyglot_key();
+        let entries = ["e4", "d4", "c4", "Nf3"]
+            .iter()
+            .enumerate()
+            .map(|(i, san)| PolyglotEntry { key, mv: encode_polyglot_move(board.parse_san(san).unwrap()), weight: 4 - i as u16, learn: 0 })
+            .collect();
+        let mut options = EngineOptions::new();
+        options.book = BookState::Loaded(OpeningBook::Polyglot(PolyglotBook::from_entries(entries)));
+
+        let mut picks = |options: &mut EngineOptions| (0..20).map(|_| options.book_move(&mut board).unwrap().to_u16()).collect::<Vec<u16>>();
+        options.set_book_seed(42);
+        let first = picks(&mut options);
+        options.set_book_seed(42);
+        assert_eq!(picks(&mut options), first);
+        assert!(first.iter().any(|&mv| mv != first[0]));
+
+        options.book_selection = BookSelection::Best;
+        assert!(picks(&mut options).iter().all(|&mv| mv == board.parse_san("e4").unwrap().to_u16()));
+
+        options.book_max_ply = 0;
+        assert_eq!(options.book_move(&mut board), None);
+    }
+
+    #[test]
+    fn parses_book_mode_combo() {
+        let (option, value) = parse_setoption("setoption name BookMode value uniform").unwrap();
+        assert_eq!(option.name, "BookMode");
+        assert_eq!(value, OptionValue::String("Uniform".to_string()));
+        assert!(parse_setoption("setoption name BookMode value sometimes").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::helpers::pop_lsb;
 use crate::Board;
+use crate::move_gen_dir::move_gen::{Castling, Move, PieceType};
 use lazy_static::lazy_static;
 use xorshift::{Rng, SeedableRng, Xorshift128};
 
@@ -11,12 +12,44 @@ lazy_static! {
 }
 
 
+const SIDE_TO_MOVE_KEY: usize = 768;
+const CASTLING_KEYS: usize = 769;
+const EN_PASSANT_KEYS: usize = 773;
+
+/// Key of `piece_type` of the given color standing on the single square bitboard `square`.
+fn piece_key(piece_type: PieceType, white: bool, square: u64) -> u64 {
+    let piece_index = piece_type as usize + if white { 0 } else { 6 };
+    ZOBRIST_NUMBERS[64 * piece_index + square.trailing_zeros() as usize]
+}
+
+fn castling_key(castling_rights: u8) -> u64 {
+    let mut key = 0;
+    for i in 0..4 {
+        if castling_rights & (1 << i) != 0 {
+            key ^= ZOBRIST_NUMBERS[CASTLING_KEYS + i];
+        }
+    }
+    key
+}
+
+fn en_passant_key(last_double_pawn_push: u64) -> u64 {
+    if last_double_pawn_push == 0 {
+        return 0;
+    }
+    ZOBRIST_NUMBERS[EN_PASSANT_KEYS + (last_double_pawn_push.trailing_zeros() % 8) as usize]
+}
+
 impl Board {
-    pub fn zobrist_hash(&self) -> u64 {
+    pub const fn zobrist_hash(&self) -> u64 {
+        self.zobrist_key
+    }
+
+    /// Computes the key from scratch, `make_move` keeps `zobrist_key` up to date incrementally.
+    pub fn compute_zobrist_hash(&self) -> u64 {
         let pieces = [self.wpawn,self.wrook,self.wknight,self.wbishop,self.wqueen,self.wking,self.bpawn,self.brook,self.bknight,self.bbishop,self.bqueen,self.bking];
         let mut zobrist_hash: u64 = 0;
         for pieces_types in 0..pieces.len() {
-            let mut piece_bb = pieces[pieces_types].clone();
+            let mut piece_bb = pieces[pieces_types];
             while piece_bb != 0 {
                 let destination = pop_lsb(&mut piece_bb);
 
@@ -24,20 +57,58 @@ impl Board {
             }
         }
         match self.white_to_move {
-            true => { zobrist_hash ^= ZOBRIST_NUMBERS[768] }
+            true => { zobrist_hash ^= ZOBRIST_NUMBERS[SIDE_TO_MOVE_KEY] }
             false => {}
         }
-        for i in 0..4 {
-            match (self.castling_rights & (1<<i)) != 0 {
-                true => {zobrist_hash ^= ZOBRIST_NUMBERS[769+i] }
-                false => {}
-            }
+        zobrist_hash ^= castling_key(self.castling_rights);
+        zobrist_hash ^= en_passant_key(self.last_double_pawn_push);
+
+        zobrist_hash
+    }
+
+    /// Drops the en passant square, used by null moves which give up the capture.
+    pub fn clear_en_passant(&mut self) {
+        self.zobrist_key ^= en_passant_key(self.last_double_pawn_push);
+        self.last_double_pawn_push = 0;
+    }
+
+    /// Flips the side to move in the key, the only change a null move makes once en passant is cleared.
+    pub(crate) fn toggle_side_to_move_key(&mut self) {
+        self.zobrist_key ^= ZOBRIST_NUMBERS[SIDE_TO_MOVE_KEY];
+    }
+
+    /// XORs the changes of `mv` into the key. Called by `make_move` after the board was updated,
+    /// with the castling rights and en passant square from before the move.
+    pub(crate) fn update_zobrist_key(&mut self, mv: Move, pre_castling_rights: u8, pre_last_double_pawn_push: u64) {
+        let white_moved = !self.white_to_move;
+        let mut key = ZOBRIST_NUMBERS[SIDE_TO_MOVE_KEY];
+
+        key ^= piece_key(mv.piece_type, white_moved, mv.start_square);
+        match mv.promotion {
+            PieceType::NoPiece => key ^= piece_key(mv.piece_type, white_moved, mv.end_square),
+            promotion => key ^= piece_key(promotion, white_moved, mv.end_square),
+        }
+
+        if mv.capture != PieceType::NoPiece {
+            let captured_square = if mv.en_passant { pre_last_double_pawn_push } else { mv.end_square };
+            key ^= piece_key(mv.capture, !white_moved, captured_square);
         }
-        if self.last_double_pawn_push != 0 {
-            zobrist_hash ^= ZOBRIST_NUMBERS[(722+ self.last_double_pawn_push % 8) as usize];
+
+        let (rook_from, rook_to) = match (white_moved, mv.castle) {
+            (true, Castling::KingSide) => (0x80, 0x20),
+            (true, Castling::QueenSide) => (0x1, 0x8),
+            (false, Castling::KingSide) => (0x8000000000000000, 0x2000000000000000),
+            (false, Castling::QueenSide) => (0x100000000000000, 0x800000000000000),
+            (_, Castling::NoCastle) => (0, 0),
+        };
+        if rook_from != 0 {
+            key ^= piece_key(PieceType::Rook, white_moved, rook_from) ^ piece_key(PieceType::Rook, white_moved, rook_to);
         }
 
-        return zobrist_hash;
+        key ^= castling_key(pre_castling_rights) ^ castling_key(self.castling_rights);
+        key ^= en_passant_key(pre_last_double_pawn_push) ^ en_passant_key(self.last_double_pawn_push);
+
+        self.zobrist_key ^= key;
     }
 }
 